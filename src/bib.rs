@@ -0,0 +1,262 @@
+// Bibliography files
+//
+// We write a .bib file next to the document (starting from a template in the
+// macros repository, if there is one), optionally seeded with entries given as
+// BibTeX or as DOIs, and then make sure the document actually uses it.
+
+use super::{
+    build, config,
+    file::DOCUMENT_CLASS_RE,
+    plan::{self, Step},
+    remote,
+    report::{self, Action, Level},
+    resource::{ResourceLocation, snapshot, try_fetch_resource},
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+lazy_static! {
+    static ref PACKAGE_RE: Regex =
+        Regex::new(r"\\(?:usepackage|RequirePackage)(?:\[[^\]]*\])?\{(?P<packages>[^}]*)\}")
+            .unwrap();
+    static ref BIBLIOGRAPHY_RE: Regex = Regex::new(r"\\(?:addbibresource|bibliography)\{").unwrap();
+}
+
+// Turn a snippet given by the user into a BibTeX entry.  Anything starting
+// with `@` is taken to be BibTeX already; anything else is taken to be a DOI
+fn bibtex_entry(snippet: &str) -> Option<String> {
+    let snippet = snippet.trim();
+    if snippet.starts_with('@') {
+        return Some(snippet.to_string());
+    }
+
+    let doi = snippet
+        .trim_start_matches("https://doi.org/")
+        .trim_start_matches("http://dx.doi.org/")
+        .trim_start_matches("doi:");
+    match remote::bibtex_from_doi(doi) {
+        Ok(entry) => Some(entry.trim().to_string()),
+        Err(e) => {
+            report::warn(format!("Could not get BibTeX for DOI {:?}: {}", doi, e));
+            None
+        }
+    }
+}
+
+/// Split text read from standard input into snippets: either a block of
+/// BibTeX, or one DOI per line
+pub fn parse_snippets(input: &str) -> Vec<String> {
+    if input.trim_start().starts_with('@') {
+        vec![input.to_string()]
+    } else {
+        input
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// Write (or add to) a bibliography file
+pub fn write_bibliography(
+    bib_path: &Path,
+    snippets: &[String],
+    loc: &ResourceLocation,
+    dry_run: bool,
+) -> Result<(), String> {
+    if dry_run {
+        // DOIs are looked up when the plan is applied
+        plan::record(Step::WriteBibliography {
+            path: bib_path.to_path_buf(),
+            entries: snippets.to_vec(),
+            location: *loc,
+            commit: snapshot(loc)?,
+        });
+        return Ok(());
+    }
+
+    let mut contents = if bib_path.exists() {
+        fs::read_to_string(bib_path).map_err(|e| format!("Cannot read {:?}: {}", bib_path, e))?
+    } else {
+        try_fetch_resource(config::BIB_TMPL_RESOURCE, loc)?.unwrap_or_default()
+    };
+
+    for entry in snippets.iter().filter_map(|snippet| bibtex_entry(snippet)) {
+        // Separate entries by a blank line
+        while !contents.is_empty() && !contents.ends_with("\n\n") {
+            contents.push('\n');
+        }
+        contents.push_str(&entry);
+        contents.push('\n');
+    }
+
+    report::action(
+        Level::Info,
+        Action::Write,
+        bib_path.display(),
+        format!("Writing bibliography {:?}", bib_path),
+    );
+    fs::write(bib_path, contents).map_err(|e| format!("Cannot write {:?}: {}", bib_path, e))
+}
+
+// Whether a document or class loads biblatex.  A \usepackage (or
+// \RequirePackage) line can load several packages, separated by commas
+fn loads_biblatex(contents: &str) -> bool {
+    PACKAGE_RE.captures_iter(contents).any(|caps| {
+        caps["packages"]
+            .split(',')
+            .any(|name| name.trim() == "biblatex")
+    })
+}
+
+// Whether a document uses biblatex, either by loading it itself or through
+// its class (if find_class can find the class that TeX would use)
+fn uses_biblatex(contents: &str, find_class: impl Fn(&str) -> Option<PathBuf>) -> bool {
+    if loads_biblatex(contents) {
+        return true;
+    }
+
+    DOCUMENT_CLASS_RE
+        .captures(contents)
+        .and_then(|caps| find_class(&caps["class"]))
+        .and_then(|cls| fs::read_to_string(cls).ok())
+        .is_some_and(|cls| loads_biblatex(&cls))
+}
+
+// Add the lines needed to use the bibliography to the contents of a document
+fn add_bibliography(contents: &str, biblatex: bool) -> Result<String, String> {
+    if !contents.contains("\\end{document}") {
+        return Err("no \\end{document} to add the bibliography before".to_string());
+    }
+
+    if biblatex {
+        if !contents.contains("\\begin{document}") {
+            return Err("no \\begin{document} to add the bibliography resource before".to_string());
+        }
+        return Ok(contents
+            .replacen(
+                "\\begin{document}",
+                &format!(
+                    "\\addbibresource{{{}}}\n\n\\begin{{document}}",
+                    config::BIB_FILE
+                ),
+                1,
+            )
+            .replacen("\\end{document}", "\\printbibliography\n\\end{document}", 1));
+    }
+
+    let stem = Path::new(config::BIB_FILE)
+        .file_stem()
+        .unwrap()
+        .to_string_lossy();
+    Ok(contents.replacen(
+        "\\end{document}",
+        &format!(
+            "\\bibliographystyle{{plain}}\n\\bibliography{{{}}}\n\\end{{document}}",
+            stem
+        ),
+        1,
+    ))
+}
+
+/// Add the lines needed to use the bibliography to a document, if it does
+/// not use one already.  Gives false if it already does
+pub fn use_bibliography(doc: &Path, dry_run: bool) -> Result<bool, String> {
+    // On a dry run, the document may only be planned so far
+    if dry_run && !doc.exists() {
+        plan::record(Step::UseBibliography {
+            path: doc.to_path_buf(),
+        });
+        return Ok(true);
+    }
+
+    let contents = fs::read_to_string(doc).map_err(|e| format!("Cannot read {:?}: {}", doc, e))?;
+    if BIBLIOGRAPHY_RE.is_match(&contents) {
+        return Ok(false);
+    }
+
+    let doc_dir = doc.parent().unwrap_or(Path::new("."));
+    let biblatex = uses_biblatex(&contents, |class| build::find_class(class, doc_dir));
+    let contents = add_bibliography(&contents, biblatex)
+        .map_err(|e| format!("Could not add bibliography to {:?}: {}", doc, e))?;
+
+    if dry_run {
+        plan::record(Step::UseBibliography {
+            path: doc.to_path_buf(),
+        });
+        return Ok(true);
+    }
+
+    report::action(
+        Level::Info,
+        Action::Write,
+        doc.display(),
+        format!("Adding bibliography to {:?}", doc),
+    );
+    fs::write(doc, contents).map_err(|e| format!("Cannot write {:?}: {}", doc, e))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = "\\documentclass{article}\n\\begin{document}\nHello\n\\end{document}\n";
+
+    #[test]
+    fn bibtex_added_before_end() {
+        let contents = add_bibliography(DOCUMENT, false).unwrap();
+        assert!(contents.ends_with(
+            "\\bibliographystyle{plain}\n\\bibliography{references}\n\\end{document}\n"
+        ));
+    }
+
+    #[test]
+    fn biblatex_resource_added_to_preamble() {
+        let contents = add_bibliography(DOCUMENT, true).unwrap();
+        assert!(contents.contains("\\addbibresource{references.bib}\n\n\\begin{document}"));
+        assert!(contents.contains("\\printbibliography\n\\end{document}"));
+    }
+
+    #[test]
+    fn no_end_document() {
+        assert!(add_bibliography("\\input{chapter}\n", false).is_err());
+        assert!(add_bibliography("\\input{chapter}\n\\end{document}", true).is_err());
+    }
+
+    #[test]
+    fn biblatex_loaded_by_document() {
+        let contents = "\\usepackage[style=apa]{biblatex}\n";
+        assert!(uses_biblatex(contents, |_| None));
+    }
+
+    #[test]
+    fn biblatex_loaded_in_package_list() {
+        assert!(loads_biblatex("\\usepackage{csquotes,biblatex}\n"));
+        assert!(loads_biblatex("\\usepackage[british]{babel, biblatex}\n"));
+        assert!(!loads_biblatex("\\usepackage{biblatex-chicago-notes}\n"));
+        assert!(!loads_biblatex("\\usepackage{natbib}\n"));
+    }
+
+    #[test]
+    fn biblatex_loaded_by_class() {
+        let cls = std::env::temp_dir().join(format!("mktex-bib-{}.cls", std::process::id()));
+        fs::write(&cls, "\\RequirePackage{csquotes,biblatex}\n").unwrap();
+
+        let uses = uses_biblatex("\\documentclass{thesis}\n", |class| {
+            (class == "thesis").then(|| cls.clone())
+        });
+        fs::remove_file(&cls).unwrap();
+        assert!(uses);
+    }
+
+    #[test]
+    fn biblatex_not_used_by_class() {
+        assert!(!uses_biblatex("\\documentclass{article}\n", |_| None));
+    }
+}
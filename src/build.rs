@@ -0,0 +1,370 @@
+// Compile a document
+//
+// We choose an engine (from the command line, the settings file, or whatever
+// is installed), run it quietly, and then summarise the errors and warnings
+// from the resulting log file rather than dumping TeX's output on the user.
+
+use super::{
+    provides, report,
+    settings::{Engine, SETTINGS},
+    texmf,
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+lazy_static! {
+    // TeXShop/TeXstudio-style magic comment, e.g. `% !TEX program = xelatex`
+    static ref MAGIC_PROGRAM_RE: Regex =
+        Regex::new(r"(?m)^%\s*!TEX\s+(?:TS-)?program\s*=\s*(?P<program>\w+)").unwrap();
+
+    // With -file-line-error, errors look like `./document.tex:12: Undefined control sequence.`
+    static ref FILE_LINE_ERROR_RE: Regex =
+        Regex::new(r"^(?P<file>[^:\s]+\.\w+):(?P<line>\d+): (?P<message>.+)$").unwrap();
+
+    static ref WARNING_RE: Regex =
+        Regex::new(r"^(?:LaTeX|Package \S+|Class \S+) Warning: .+$").unwrap();
+
+    static ref RERUN_RE: Regex =
+        Regex::new(r"(?:Rerun to get|Label\(s\) may have changed)").unwrap();
+}
+
+/// Errors and warnings parsed from a TeX log file
+#[derive(Default)]
+struct LogSummary {
+    errors: Vec<String>,
+    warnings: Vec<String>,
+    bad_boxes: usize,
+}
+
+impl Engine {
+    fn program(&self) -> &'static str {
+        match self {
+            Engine::Latexmk => "latexmk",
+            Engine::Tectonic => "tectonic",
+            Engine::Pdflatex => "pdflatex",
+            Engine::Xelatex => "xelatex",
+            Engine::Lualatex => "lualatex",
+        }
+    }
+
+    fn from_program(program: &str) -> Option<Self> {
+        match program.to_lowercase().as_str() {
+            "pdflatex" => Some(Engine::Pdflatex),
+            "xelatex" => Some(Engine::Xelatex),
+            "lualatex" => Some(Engine::Lualatex),
+            _ => None,
+        }
+    }
+}
+
+fn available(program: &str) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+// Get the TeX programme requested by a magic comment in the document
+fn magic_program(contents: &str) -> Option<Engine> {
+    MAGIC_PROGRAM_RE
+        .captures(contents)
+        .and_then(|caps| Engine::from_program(&caps["program"]))
+}
+
+// Choose an engine: one explicitly requested, then the settings file, then
+// latexmk (which drives the magic comment's programme), then the programme
+// named in the magic comment, then anything else that is installed
+fn choose_engine(requested: Option<Engine>, program: Option<Engine>) -> Option<Engine> {
+    if let Some(engine) = requested.or(SETTINGS.build.engine) {
+        return Some(engine);
+    }
+
+    [Some(Engine::Latexmk), program, Some(Engine::Tectonic)]
+        .into_iter()
+        .flatten()
+        .chain([Engine::Pdflatex, Engine::Xelatex, Engine::Lualatex])
+        .find(|engine| available(engine.program()))
+}
+
+// Resources installed with --install-to project live in a subdirectory of the
+// document's directory, which TeX needs to be told to search.  The path
+// returned is relative to the document's directory
+fn project_resources_dir(doc_dir: &Path) -> Option<PathBuf> {
+    let dir = Path::new(".").join(&SETTINGS.install.project_dir);
+    doc_dir.join(&dir).is_dir().then_some(dir)
+}
+
+/// The class file that TeX will use for a document in the given directory:
+/// one installed into the project, or else one on TeX's search path
+pub(crate) fn find_class(class: &str, doc_dir: &Path) -> Option<PathBuf> {
+    let file_name = format!("{}.cls", class);
+    project_resources_dir(doc_dir)
+        .map(|dir| doc_dir.join(dir).join(&file_name))
+        .filter(|path| path.exists())
+        .or_else(|| texmf::kpsewhich(&file_name))
+}
+
+fn command(engine: Engine, program: Option<Engine>, file_name: &str, doc_dir: &Path) -> Command {
+    let mut cmd = Command::new(engine.program());
+    match engine {
+        Engine::Latexmk => {
+            cmd.arg(match program {
+                Some(Engine::Xelatex) => "-xelatex",
+                Some(Engine::Lualatex) => "-lualatex",
+                _ => "-pdf",
+            });
+            cmd.args([
+                "-interaction=nonstopmode",
+                "-halt-on-error",
+                "-file-line-error",
+            ]);
+        }
+        Engine::Tectonic => {
+            cmd.arg("--keep-logs");
+            if let Some(dir) = project_resources_dir(doc_dir) {
+                cmd.arg(format!("-Zsearch-path={}", dir.display()));
+            }
+        }
+        Engine::Pdflatex | Engine::Xelatex | Engine::Lualatex => {
+            cmd.args(["-interaction=nonstopmode", "-file-line-error"]);
+        }
+    }
+    cmd.arg(file_name);
+
+    // The trailing colon means "and then the default search path"
+    if let Some(dir) = project_resources_dir(doc_dir) {
+        let existing = env::var("TEXINPUTS").unwrap_or_default();
+        cmd.env("TEXINPUTS", format!("{}//:{}", dir.display(), existing));
+    }
+
+    cmd.current_dir(doc_dir);
+    cmd
+}
+
+fn doc_dir(doc: &Path) -> PathBuf {
+    match doc.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+// Warn if the document asks for a newer release of its class than the one
+// TeX will find
+fn check_class_date(contents: &str, doc_dir: &Path) {
+    let Some(class) = provides::requested_class(contents) else {
+        return;
+    };
+    let Some(cls_provides) = find_class(&class, doc_dir)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|cls| provides::parse(&cls))
+    else {
+        return;
+    };
+
+    if let Some((class, date)) = provides::newer_date_requested(contents, &cls_provides) {
+        report::warn(format!(
+            "Document requests class {} from {} or later, but the installed version is from {}",
+            class,
+            date,
+            cls_provides.date.as_deref().unwrap_or("an unknown date")
+        ));
+    }
+}
+
+/// Parse errors and warnings from the contents of a TeX log file
+fn summarise_log(log: &str) -> LogSummary {
+    let mut summary = LogSummary::default();
+    let lines: Vec<&str> = log.lines().collect();
+
+    for (i, line) in lines.iter().enumerate() {
+        if let Some(caps) = FILE_LINE_ERROR_RE.captures(line) {
+            summary.errors.push(format!(
+                "{}:{}: {}",
+                &caps["file"], &caps["line"], &caps["message"]
+            ));
+        } else if let Some(message) = line.strip_prefix("! ") {
+            // Without -file-line-error, the line number follows shortly after
+            let location = lines[i + 1..]
+                .iter()
+                .take(5)
+                .find_map(|line| line.strip_prefix("l."))
+                .and_then(|line| line.split_whitespace().next())
+                .map(|line_no| format!(" (line {})", line_no))
+                .unwrap_or_default();
+            summary.errors.push(format!("{}{}", message, location));
+        } else if WARNING_RE.is_match(line) {
+            summary.warnings.push(line.to_string());
+        } else if line.starts_with("Overfull \\") || line.starts_with("Underfull \\") {
+            summary.bad_boxes += 1;
+        }
+    }
+
+    summary
+}
+
+// Whether a log says that the document needs compiling again, e.g., for
+// cross-references
+fn needs_rerun(log: &str) -> bool {
+    RERUN_RE.is_match(log)
+}
+
+/// Compile a document, printing a summary of the build.  Gives false if the
+/// document did not compile, and fails if it could not be compiled at all
+/// (e.g., because there is no engine to compile it with)
+pub fn build(doc: &Path, requested: Option<Engine>) -> Result<bool, String> {
+    if !doc.exists() {
+        return Err(format!("No such document {:?}", doc));
+    }
+    let file_name = doc
+        .file_name()
+        .ok_or_else(|| format!("{:?} is not a document", doc))?
+        .to_string_lossy()
+        .into_owned();
+
+    let contents =
+        fs::read_to_string(doc).map_err(|e| format!("Could not read {:?}: {}", doc, e))?;
+    check_class_date(&contents, &doc_dir(doc));
+    let program = magic_program(&contents);
+    let engine = choose_engine(requested, program)
+        .ok_or("Could not find latexmk, tectonic, or a LaTeX engine to build with")?;
+
+    let doc_dir = doc_dir(doc);
+    let log_path = doc.with_extension("log");
+
+    report::info(format!("Building {:?} with {}", doc, engine.program()));
+
+    // latexmk and tectonic rerun as needed; plain engines need rerunning by hand
+    let max_runs = match engine {
+        Engine::Latexmk | Engine::Tectonic => 1,
+        _ => 3,
+    };
+    let mut output = None;
+    for _ in 0..max_runs {
+        let run = command(engine, program, &file_name, &doc_dir)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| format!("Failed to execute `{}`: {}", engine.program(), e))?;
+        let success = run.status.success();
+        output = Some(run);
+
+        let rerun = fs::read_to_string(&log_path).is_ok_and(|log| needs_rerun(&log));
+        if !success || !rerun {
+            break;
+        }
+    }
+    let output = output.unwrap();
+
+    let summary = fs::read_to_string(&log_path)
+        .map(|log| summarise_log(&log))
+        .unwrap_or_default();
+
+    if !summary.warnings.is_empty() {
+        report::warn(format!("{} warning(s):", summary.warnings.len()));
+        for warning in &summary.warnings {
+            report::warn(format!("    {}", warning));
+        }
+    }
+    if summary.bad_boxes > 0 {
+        report::warn(format!(
+            "{} overfull or underfull box(es)",
+            summary.bad_boxes
+        ));
+    }
+
+    if output.status.success() {
+        report::info(format!("Built {:?}", doc.with_extension("pdf")));
+        return Ok(true);
+    }
+
+    if summary.errors.is_empty() {
+        // Nothing useful in the log (or no log at all), so show the end of the
+        // engine's own output instead
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let lines: Vec<&str> = stdout.lines().chain(stderr.lines()).collect();
+        report::error("Build failed:");
+        for line in &lines[lines.len().saturating_sub(20)..] {
+            report::error(format!("    {}", line));
+        }
+    } else {
+        report::error(format!(
+            "Build failed with {} error(s):",
+            summary.errors.len()
+        ));
+        for error in &summary.errors {
+            report::error(format!("    {}", error));
+        }
+    }
+
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_line_errors() {
+        let log = "(./document.tex\n./document.tex:12: Undefined control sequence.\nl.12 \\foo\n";
+        let summary = summarise_log(log);
+        assert_eq!(
+            summary.errors,
+            ["./document.tex:12: Undefined control sequence."]
+        );
+    }
+
+    #[test]
+    fn error_line_number_found_after_message() {
+        let log =
+            "! Missing $ inserted.\n<inserted text>\n                $\nl.27 x^\n          2\n";
+        let summary = summarise_log(log);
+        assert_eq!(summary.errors, ["Missing $ inserted. (line 27)"]);
+    }
+
+    #[test]
+    fn error_without_line_number() {
+        let summary = summarise_log("! Emergency stop.\n*** (job aborted, no legal \\end found)\n");
+        assert_eq!(summary.errors, ["Emergency stop."]);
+    }
+
+    #[test]
+    fn warnings_and_bad_boxes() {
+        let log = "LaTeX Warning: Reference `fig:plot' on page 1 undefined on input line 8.\n\
+                   Package hyperref Warning: Token not allowed in a PDF string.\n\
+                   Overfull \\hbox (12.0pt too wide) in paragraph at lines 3--4\n\
+                   Underfull \\vbox (badness 10000) has occurred while \\output is active\n\
+                   Some other line\n";
+        let summary = summarise_log(log);
+        assert!(summary.errors.is_empty());
+        assert_eq!(summary.warnings.len(), 2);
+        assert!(summary.warnings[1].starts_with("Package hyperref Warning:"));
+        assert_eq!(summary.bad_boxes, 2);
+    }
+
+    #[test]
+    fn rerun_detected() {
+        assert!(needs_rerun(
+            "LaTeX Warning: Label(s) may have changed. Rerun to get cross-references right.\n"
+        ));
+        assert!(needs_rerun(
+            "Package rerunfilecheck Warning: File `document.out' has changed.\n(rerunfilecheck) Rerun to get outlines right\n"
+        ));
+        assert!(!needs_rerun("Output written on document.pdf (1 page).\n"));
+    }
+
+    #[test]
+    fn magic_comment_engine() {
+        assert!(matches!(
+            magic_program("% !TEX program = xelatex\n\\documentclass{article}"),
+            Some(Engine::Xelatex)
+        ));
+        assert!(magic_program("% !TEX program = context\n").is_none());
+    }
+}
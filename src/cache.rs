@@ -0,0 +1,133 @@
+// Responses cached on disk
+//
+// Responses from the resource repository that carry an ETag or Last-Modified
+// header are kept under ~/.cache/mktex (or $XDG_CACHE_HOME/mktex), one JSON
+// file per URL.  The next request for the same URL sends them back
+// (If-None-Match/If-Modified-Since), so that, when nothing has changed, the
+// server only answers 304 Not Modified and the cached copy is used instead of
+// downloading it again.  (Conditional requests answered with 304 also do not
+// count against GitHub's API rate limit.)  Cached copies of responses that
+// cannot change (such as a file at a given commit) are also used when the
+// server cannot be reached at all; responses that change over time (such as
+// which commit is the latest) are not, as an old copy would be misleading.
+//
+// Entries are never removed automatically; `mktex cache clear` removes them
+// all.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Entry {
+    /// What the response was for (the request's URL and media type)
+    pub key: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: String,
+}
+
+/// Directory that responses are cached in
+pub fn cache_dir() -> Option<PathBuf> {
+    if let Ok(dir) = env::var("XDG_CACHE_HOME")
+        && !dir.is_empty()
+    {
+        return Some(PathBuf::from(dir).join("mktex"));
+    }
+
+    home::home_dir().map(|home| home.join(".cache").join("mktex"))
+}
+
+fn entry_path(key: &str) -> Option<PathBuf> {
+    let mut hasher = Sha256::new();
+    hasher.update(key.as_bytes());
+    Some(cache_dir()?.join(format!("{:x}.json", hasher.finalize())))
+}
+
+/// The cached response for a key, if any
+pub(crate) fn lookup(key: &str) -> Option<Entry> {
+    let contents = fs::read_to_string(entry_path(key)?).ok()?;
+    let entry: Entry = serde_json::from_str(&contents).ok()?;
+    (entry.key == key).then_some(entry)
+}
+
+/// Cache a response.  Failing to is not an error: the response is just
+/// downloaded in full next time
+pub(crate) fn store(entry: &Entry) {
+    let Some(path) = entry_path(&entry.key) else {
+        return;
+    };
+    if let Some(parent) = path.parent()
+        && fs::create_dir_all(parent).is_err()
+    {
+        return;
+    }
+
+    // Write to a temporary file first, so that another mktex running at the
+    // same time never reads half an entry
+    let tmp = path.with_extension(format!("json.{}.tmp", process::id()));
+    let contents = serde_json::to_string(entry).unwrap();
+    if fs::write(&tmp, contents).is_err() || fs::rename(&tmp, &path).is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+}
+
+/// Remove every cached response.  Returns how many were removed
+pub fn clear() -> Result<usize, String> {
+    let dir = cache_dir().ok_or("Cannot find the cache directory")?;
+    clear_dir(&dir).map_err(|e| format!("Cannot clear {:?}: {}", dir, e))
+}
+
+// Remove the entries (and any temporary files left behind) in a directory,
+// leaving anything else alone
+fn clear_dir(dir: &Path) -> io::Result<usize> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let mut removed = 0;
+    for entry in entries {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.ends_with(".json") || name.ends_with(".tmp") {
+            fs::remove_file(&path)?;
+            removed += name.ends_with(".json") as usize;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clear_removes_entries_only() {
+        let dir = env::temp_dir().join(format!("mktex-cache-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("0123.json"), "{}").unwrap();
+        fs::write(dir.join("4567.json.42.tmp"), "{").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let removed = clear_dir(&dir).unwrap();
+        let left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(removed, 1);
+        assert_eq!(left, ["notes.txt"]);
+    }
+
+    #[test]
+    fn clear_missing_directory() {
+        let dir = env::temp_dir().join("mktex-cache-does-not-exist");
+        assert_eq!(clear_dir(&dir).unwrap(), 0);
+    }
+}
@@ -0,0 +1,143 @@
+// Discover what the resource repository offers
+//
+// We list the classes, packages, and templates under class/ and templates/,
+// along with a short description of each.  For the local backend this is a
+// directory walk; for the remote backend we use GitHub's Git trees API, falling
+// back to a manifest file at the root of the repository (one path per line) if
+// the API is unavailable (e.g., because of rate limiting).
+
+use super::{
+    config,
+    file::DOCUMENT_CLASS_RE,
+    local, provides, remote, report,
+    resource::{self, ResourceLocation, fetch_resource_head, try_fetch_resource},
+};
+use std::thread;
+
+pub struct CatalogueEntry {
+    pub path: String,
+    pub description: String,
+}
+
+fn is_listed(path: &str) -> bool {
+    let in_dir = path.starts_with(config::RESOURCE_PARENT) || path.starts_with(config::TMPL_PARENT);
+    let ext = path.rsplit('.').next().unwrap_or_default();
+    in_dir && matches!(ext, "cls" | "sty" | "tex")
+}
+
+// List every file in the resource repository
+fn resource_files(loc: &ResourceLocation) -> Result<Vec<String>, String> {
+    match loc {
+        ResourceLocation::Local => Ok(local::list_local_files()?
+            .into_iter()
+            .map(|path| path.display().to_string())
+            .collect()),
+        ResourceLocation::Remote => match remote::list_remote_files(&resource::snapshot(loc)?) {
+            Ok(files) => Ok(files),
+            Err(e) => {
+                report::warn(format!(
+                    "Could not list remote files ({}); trying {}",
+                    e,
+                    config::MANIFEST_RESOURCE
+                ));
+                let manifest = try_fetch_resource(config::MANIFEST_RESOURCE, loc)?
+                    .ok_or("Could not list resources in the remote repository")?;
+                Ok(manifest
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_string)
+                    .collect())
+            }
+        },
+    }
+}
+
+// Describe a resource: classes and packages describe themselves in their
+// \Provides line; templates are described by the class they use
+fn describe(path: &str, contents: &str) -> String {
+    if let Some(provides) = provides::parse(contents) {
+        return match &provides.description {
+            Some(description) => format!("{}: {}", provides, description),
+            None => provides.to_string(),
+        };
+    }
+    if let Some(caps) = DOCUMENT_CLASS_RE.captures(contents) {
+        return format!("template using class {}", &caps["class"]);
+    }
+    if path.ends_with(".tex") && path.starts_with(config::RESOURCE_PARENT) {
+        return "input file".to_string();
+    }
+    String::new()
+}
+
+// Describe a resource from the start of it, where its \Provides line or
+// \documentclass is.  Resources that cannot be read (or are not text) are
+// left out
+fn entry(path: String, loc: &ResourceLocation) -> Option<CatalogueEntry> {
+    let Some(head) = fetch_resource_head(&path, loc).ok().flatten() else {
+        report::debug(format!("Cannot read {:?}; leaving it out", path));
+        return None;
+    };
+    let description = describe(&path, &head);
+    Some(CatalogueEntry { path, description })
+}
+
+/// List the resources available in the resource repository
+pub fn catalogue(loc: &ResourceLocation) -> Result<Vec<CatalogueEntry>, String> {
+    let mut paths: Vec<String> = resource_files(loc)?
+        .into_iter()
+        .filter(|path| is_listed(path))
+        .collect();
+    paths.sort();
+
+    // Every fetch needs to know which commit to fetch at
+    resource::snapshot(loc)?;
+
+    let mut entries = Vec::new();
+    thread::scope(|scope| {
+        for chunk in paths.chunks(config::MAX_PARALLEL_FETCHES) {
+            let handles: Vec<_> = chunk
+                .iter()
+                .map(|path| scope.spawn(move || entry(path.clone(), loc)))
+                .collect();
+            entries.extend(
+                handles
+                    .into_iter()
+                    .filter_map(|handle| handle.join().unwrap()),
+            );
+        }
+    });
+    Ok(entries)
+}
+
+/// Print the available resources, optionally only those matching a search
+/// term (in their path or description)
+pub fn print_catalogue(loc: &ResourceLocation, term: Option<&str>) -> Result<(), String> {
+    let term = term.map(str::to_lowercase);
+    let entries: Vec<CatalogueEntry> = catalogue(loc)?
+        .into_iter()
+        .filter(|entry| match &term {
+            Some(term) => {
+                entry.path.to_lowercase().contains(term)
+                    || entry.description.to_lowercase().contains(term)
+            }
+            None => true,
+        })
+        .collect();
+
+    let width = entries
+        .iter()
+        .map(|entry| entry.path.len())
+        .max()
+        .unwrap_or(0);
+    for entry in &entries {
+        report::output(format!(
+            "{:<width$}  {}",
+            entry.path,
+            entry.description,
+            width = width
+        ));
+    }
+    Ok(())
+}
@@ -3,11 +3,39 @@ pub const GITHUB_USER: &str = "jakewilliami";
 pub const GITHUB_REPO_NAME: &str = "tex-macros";
 pub const MAIN_BRANCH: &str = "master";
 pub const SHORT_HASH_LENGTH: usize = 7;
+pub const FULL_HASH_LENGTH: usize = 40;
+pub const DIRTY_SUFFIX: &str = "-dirty";
+pub const MAX_PARALLEL_FETCHES: usize = 8;
+
+// Resource Listing
+pub const TMPL_PARENT: &str = "templates/";
+pub const MANIFEST_RESOURCE: &str = "manifest.txt";
+// How much of each resource to read to describe it
+pub const HEAD_LENGTH: usize = 4096;
 
 // Base Resource Path
 pub const RESOURCE_ROOT: &str = "projects/";
 pub const RESOURCE_PARENT: &str = "class/";
 
+// Install Path (relative to TEXMFHOME)
+pub const TEXMF_LOCAL_ROOT: &str = "tex/latex/local";
+
+// Project Build Configuration (latexmk reads only one of latexmkrc and
+// .latexmkrc, so we always use this one)
+pub const LATEXMKRC: &str = ".latexmkrc";
+
+// Scaffold Manifests (one per document kind, e.g., templates/scaffold/article)
+pub const SCAFFOLD_PATH: &str = "templates/scaffold/";
+
+// Bibliography Resources
+pub const BIB_FILE: &str = "references.bib";
+pub const BIB_TMPL_RESOURCE: &str = "templates/references.bib";
+
+// Figure Resources (templates are optional, e.g., templates/figure/tikz.tex)
+pub const FIG_DIR: &str = "figures/";
+pub const FIG_TMPL_PATH: &str = "templates/figure/";
+pub const FIG_MARKER: &str = "% mktex:figures";
+
 // Arteacle Resources
 pub const CLS_RESOURCE: &str = "class/arteacle.cls";
 pub const TMPL_RESOURCE: &str = "templates/arteacle.tex";
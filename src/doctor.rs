@@ -0,0 +1,316 @@
+// Diagnose the environment mktex runs in
+//
+// mktex relies on a handful of external programmes and services (git,
+// kpsewhich, a writable texmf directory, the local macros checkout, and
+// GitHub).  When any of these is missing, the failure is usually reported far
+// from its cause, so here we check each of them up front and explain how to
+// fix anything that is wrong.
+
+use super::{
+    config, local, remote, report,
+    resource::{ResourceLocation, Source},
+    settings::SETTINGS,
+    texmf,
+};
+use chrono::prelude::*;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+};
+
+#[derive(PartialEq)]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+    hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Warn,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Fail,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+// Get the first line of `<prog> --version`, if the programme can be run
+fn command_version(prog: &str) -> Option<String> {
+    let output = Command::new(prog)
+        .arg("--version")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+}
+
+fn check_git() -> Check {
+    match command_version("git") {
+        Some(version) => Check::pass("git", version),
+        None => Check::warn(
+            "git",
+            "not found",
+            "Install git; it is needed to stamp documents with the commit of local resources (--local)",
+        ),
+    }
+}
+
+fn check_kpsewhich() -> Check {
+    match command_version("kpsewhich") {
+        Some(version) => Check::pass("kpsewhich", version),
+        None => Check::fail(
+            "kpsewhich",
+            "not found",
+            "Install a TeX distribution (e.g., TeX Live) and ensure its binaries are on your PATH",
+        ),
+    }
+}
+
+fn check_texmf(source: &Source) -> Vec<Check> {
+    let Some(texmf_home) = texmf::texmf() else {
+        return vec![Check::fail(
+            "TEXMFHOME",
+            "could not be determined",
+            "Check the output of `kpsewhich -var-value TEXMFHOME`",
+        )];
+    };
+    let mut checks = vec![Check::pass("TEXMFHOME", texmf_home.display().to_string())];
+
+    // Check that we can write to the directory into which resources are
+    // installed, or, if it does not exist yet, make it
+    let install_dir = texmf_home.join(SETTINGS.layout.root(&source.user, &source.repo));
+    checks.push(match probe_writable(&install_dir) {
+        Ok(dir) if dir == install_dir => {
+            Check::pass("texmf writable", install_dir.display().to_string())
+        }
+        Ok(dir) => Check::pass(
+            "texmf writable",
+            format!(
+                "{} does not exist yet, but can be made in {}",
+                install_dir.display(),
+                dir.display()
+            ),
+        ),
+        Err((dir, e)) => Check::fail(
+            "texmf writable",
+            format!("{}: {}", dir.display(), e),
+            "Fix the permissions of TEXMFHOME, or use --install-to project",
+        ),
+    });
+
+    // If the tree has a filename database, we need to be able to refresh it
+    if texmf::has_ls_r(&texmf_home) {
+        let refresher = ["mktexlsr", "texhash"]
+            .into_iter()
+            .find(|prog| command_version(prog).is_some());
+        checks.push(match refresher {
+            Some(prog) => Check::pass("ls-R refresh", prog),
+            None => Check::warn(
+                "ls-R refresh",
+                "mktexlsr not found",
+                format!(
+                    "Run `mktexlsr {}` by hand after installing resources",
+                    texmf_home.display()
+                ),
+            ),
+        });
+    }
+
+    checks
+}
+
+// Check that we can write to a directory (or, if it does not exist, to the
+// nearest directory above it that does, in which it would be made) by making
+// and removing a file in it.  Gives the directory written to
+fn probe_writable(dir: &Path) -> Result<PathBuf, (PathBuf, io::Error)> {
+    let existing = dir
+        .ancestors()
+        .find(|ancestor| ancestor.is_dir())
+        .unwrap_or(dir)
+        .to_path_buf();
+    let probe = existing.join(format!(".mktex-doctor-{}", process::id()));
+    match fs::write(&probe, "").and_then(|_| fs::remove_file(&probe)) {
+        Ok(()) => Ok(existing),
+        Err(e) => Err((existing, e)),
+    }
+}
+
+fn check_local_checkout() -> Check {
+    let resource_dir = local::local_resource_dir();
+    if !resource_dir.exists() {
+        return Check::warn(
+            "local checkout",
+            format!("{} not found", resource_dir.display()),
+            format!(
+                "Clone https://github.com/{}/{} to {} (or set `path` under [local] in your settings) to use --local",
+                config::GITHUB_USER,
+                config::GITHUB_REPO_NAME,
+                resource_dir.display()
+            ),
+        );
+    }
+
+    let is_repo = Command::new("git")
+        .arg("-C")
+        .arg(&resource_dir)
+        .args(["rev-parse", "--verify", "HEAD"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    if is_repo {
+        Check::pass("local checkout", resource_dir.display().to_string())
+    } else {
+        Check::warn(
+            "local checkout",
+            format!("{} is not a git repository", resource_dir.display()),
+            "Documents made with --local cannot be stamped with a commit hash",
+        )
+    }
+}
+
+fn check_network() -> Check {
+    let resource = config::CLS_RESOURCE;
+    match remote::remote_resource_reachable(resource, config::MAIN_BRANCH) {
+        Ok(()) => Check::pass("network", "raw.githubusercontent.com reachable"),
+        Err(e) => Check::fail(
+            "network",
+            format!("cannot fetch {}: {}", resource, e),
+            "Check your internet connection, or use --local",
+        ),
+    }
+}
+
+fn check_rate_limit() -> Check {
+    match remote::rate_limit() {
+        Ok(rate_limit) => {
+            let reset = DateTime::from_timestamp(rate_limit.reset, 0)
+                .map(|dt| dt.with_timezone(&Local).format("%H:%M").to_string())
+                .unwrap_or_else(|| rate_limit.reset.to_string());
+            let detail = format!(
+                "{}/{} requests remaining (resets at {})",
+                rate_limit.remaining, rate_limit.limit, reset
+            );
+            if rate_limit.remaining == 0 {
+                Check::fail(
+                    "GitHub API",
+                    detail,
+                    format!("Wait until {} or use --local", reset),
+                )
+            } else {
+                Check::pass("GitHub API", detail)
+            }
+        }
+        Err(e) => Check::fail(
+            "GitHub API",
+            e,
+            "Check that api.github.com is reachable from your network",
+        ),
+    }
+}
+
+/// Run every check (against resources from the given location) and print the
+/// results.  Fails if anything that mktex requires is missing
+pub fn run(loc: &ResourceLocation) -> Result<(), String> {
+    let mut checks = vec![check_git(), check_kpsewhich()];
+    if checks[1].status == Status::Pass {
+        checks.extend(check_texmf(&Source::of(loc)));
+    }
+    checks.push(check_local_checkout());
+    checks.push(check_network());
+    checks.push(check_rate_limit());
+
+    let width = checks
+        .iter()
+        .map(|check| check.name.len())
+        .max()
+        .unwrap_or(0);
+    for check in &checks {
+        let status = match check.status {
+            Status::Pass => "pass",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        };
+        report::output(format!(
+            "{:<width$}  {}  {}",
+            check.name,
+            status,
+            check.detail,
+            width = width
+        ));
+        if let Some(hint) = &check.hint {
+            report::output(format!("{:<width$}        {}", "", hint, width = width));
+        }
+    }
+
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == Status::Fail)
+        .count();
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!(
+            "{} check(s) failed; see the hints above to fix them",
+            failed
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn writable_directory() {
+        let dir = env::temp_dir();
+        assert_eq!(probe_writable(&dir).unwrap(), dir);
+    }
+
+    #[test]
+    fn missing_directory_probed_in_existing_ancestor() {
+        let dir = env::temp_dir();
+        let missing = dir
+            .join(format!("mktex-doctor-{}", process::id()))
+            .join("tex");
+        assert_eq!(probe_writable(&missing).unwrap(), dir);
+        assert!(!missing.exists());
+    }
+}
@@ -0,0 +1,252 @@
+// Figures as standalone documents
+//
+// Each figure lives in its own standalone-class document under figures/, so
+// that it can be compiled (and iterated on) separately from the main document.
+// The main document then pulls the figure in with \includestandalone (or
+// \includegraphics of the compiled PDF), inserted at a marker comment:
+//
+//     % mktex:figures
+//
+// If the document has no marker, the figure is placed at the end of the
+// document body instead.
+
+use super::{
+    config,
+    plan::{self, Step},
+    report::{self, Action, Level},
+    resource::{ResourceLocation, snapshot, try_fetch_resource},
+};
+use clap::ValueEnum;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+const TIKZ_FIGURE: &str = "\
+\\documentclass[tikz]{standalone}
+
+\\begin{document}
+\\begin{tikzpicture}
+
+\\end{tikzpicture}
+\\end{document}
+";
+
+const PGFPLOTS_FIGURE: &str = "\
+\\documentclass[tikz]{standalone}
+\\usepackage{pgfplots}
+\\pgfplotsset{compat=newest}
+
+\\begin{document}
+\\begin{tikzpicture}
+\\begin{axis}
+
+\\end{axis}
+\\end{tikzpicture}
+\\end{document}
+";
+
+lazy_static! {
+    static ref USEPACKAGE_RE: Regex =
+        Regex::new(r"\\usepackage(?:\[[^\]]*\])?\{(?P<packages>[^}]*)\}").unwrap();
+}
+
+#[derive(ValueEnum, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FigureStyle {
+    /// A TikZ picture
+    Tikz,
+    /// A pgfplots axis
+    Pgfplots,
+}
+
+impl FigureStyle {
+    fn name(&self) -> &'static str {
+        match self {
+            FigureStyle::Tikz => "tikz",
+            FigureStyle::Pgfplots => "pgfplots",
+        }
+    }
+
+    fn default_source(&self) -> &'static str {
+        match self {
+            FigureStyle::Tikz => TIKZ_FIGURE,
+            FigureStyle::Pgfplots => PGFPLOTS_FIGURE,
+        }
+    }
+}
+
+// Load a package in the document's preamble if it does not already.  A
+// \usepackage line can load several packages, separated by commas
+fn ensure_package(contents: String, package: &str) -> String {
+    let loaded = USEPACKAGE_RE.captures_iter(&contents).any(|caps| {
+        caps["packages"]
+            .split(',')
+            .any(|name| name.trim() == package)
+    });
+    if loaded {
+        return contents;
+    }
+
+    contents.replacen(
+        "\\begin{document}",
+        &format!("\\usepackage{{{}}}\n\\begin{{document}}", package),
+        1,
+    )
+}
+
+/// Insert the figure environment into the main document
+pub(crate) fn insert_figure(
+    doc: &Path,
+    name: &str,
+    style: FigureStyle,
+    graphics: bool,
+    dry_run: bool,
+) -> Result<(), String> {
+    // On a dry run, the document may only be planned so far
+    if dry_run {
+        plan::record(Step::AddFigure {
+            doc: doc.to_path_buf(),
+            name: name.to_string(),
+            style,
+            graphics,
+        });
+        return Ok(());
+    }
+
+    let Ok(contents) = fs::read_to_string(doc) else {
+        report::warn(format!(
+            "Could not read {:?}; figure not added to document",
+            doc
+        ));
+        return Ok(());
+    };
+
+    let figure_path = format!("{}{}", config::FIG_DIR, name);
+    let (include, package) = if graphics {
+        (format!("\\includegraphics{{{}}}", figure_path), "graphicx")
+    } else {
+        (
+            format!("\\includestandalone{{{}}}", figure_path),
+            "standalone",
+        )
+    };
+    let figure = format!(
+        "\\begin{{figure}}[htbp]\n    \\centering\n    {}\n    \\caption{{}}\n    \\label{{fig:{}}}\n\\end{{figure}}\n",
+        include, name
+    );
+
+    let (anchor, location) = if contents.contains(config::FIG_MARKER) {
+        (config::FIG_MARKER, "at marker")
+    } else {
+        ("\\end{document}", "at end of document")
+    };
+
+    // Insert before the anchor so that the marker stays put for the next figure
+    let contents = contents.replacen(anchor, &format!("{}\n{}", figure, anchor), 1);
+    let mut contents = ensure_package(contents, package);
+    if !graphics {
+        // \includestandalone inputs the figure's code, so the document needs
+        // the figure's own packages too
+        contents = ensure_package(contents, "tikz");
+        if let FigureStyle::Pgfplots = style {
+            contents = ensure_package(contents, "pgfplots");
+        }
+    }
+
+    report::action(
+        Level::Info,
+        Action::Write,
+        doc.display(),
+        format!("Adding figure {:?} to {:?} ({})", name, doc, location),
+    );
+    fs::write(doc, contents).map_err(|e| format!("Cannot write {:?}: {}", doc, e))
+}
+
+/// Make a standalone figure source in the figures directory next to the
+/// document, and add it to the document.  Fails if the figure already exists
+pub fn make_figure(
+    name: &str,
+    doc: &Path,
+    style: FigureStyle,
+    template: Option<&str>,
+    graphics: bool,
+    loc: &ResourceLocation,
+    dry_run: bool,
+) -> Result<(), String> {
+    let doc_dir = doc.parent().unwrap_or(Path::new("."));
+    let figure_dir = doc_dir.join(config::FIG_DIR);
+    let figure_file = figure_dir.join(format!("{}.tex", name));
+
+    if figure_file.exists() {
+        return Err(format!("Figure {:?} already exists", &figure_file));
+    }
+
+    // Use a template from the macros repository if there is one
+    let template_path = template
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{}{}.tex", config::FIG_TMPL_PATH, style.name()));
+    if dry_run {
+        plan::record(Step::WriteFile {
+            path: figure_file,
+            resource: Some(template_path),
+            location: *loc,
+            commit: snapshot(loc)?,
+            contents: style.default_source().to_string(),
+        });
+    } else {
+        let source = try_fetch_resource(&template_path, loc)?.unwrap_or_else(|| {
+            if template.is_some() {
+                report::warn(format!(
+                    "Could not find figure template {:?}; using default",
+                    &template_path
+                ));
+            }
+            style.default_source().to_string()
+        });
+
+        report::action(
+            Level::Info,
+            Action::Write,
+            figure_file.display(),
+            format!("Writing figure {:?}", &figure_file),
+        );
+        fs::create_dir_all(&figure_dir)
+            .and_then(|_| fs::write(&figure_file, source))
+            .map_err(|e| format!("Cannot write {:?}: {}", &figure_file, e))?;
+    }
+
+    insert_figure(doc, name, style, graphics, dry_run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = "\\documentclass{article}\n\\usepackage{amsmath, tikz-cd}\n\\begin{document}\n\\end{document}\n";
+
+    #[test]
+    fn package_added() {
+        let contents = ensure_package(DOCUMENT.to_string(), "graphicx");
+        assert!(contents.contains("\\usepackage{graphicx}\n\\begin{document}"));
+    }
+
+    #[test]
+    fn package_in_list_not_added_again() {
+        let contents = ensure_package(DOCUMENT.to_string(), "amsmath");
+        assert_eq!(contents, DOCUMENT);
+    }
+
+    #[test]
+    fn package_with_options_not_added_again() {
+        let document = DOCUMENT.replace("{amsmath, tikz-cd}", "[draft]{\n  graphicx,\n  xcolor\n}");
+        let contents = ensure_package(document.clone(), "graphicx");
+        assert_eq!(contents, document);
+    }
+
+    #[test]
+    fn package_prefix_not_mistaken_for_package() {
+        let contents = ensure_package(DOCUMENT.to_string(), "tikz");
+        assert!(contents.contains("\\usepackage{tikz}\n\\begin{document}"));
+    }
+}
@@ -1,37 +1,114 @@
 use super::{
-    config, local, remote,
-    resource::{ResourceLocation, fetch_resource},
-    sync, texmf,
+    config,
+    plan::{self, Step},
+    prompt, provides,
+    report::{self, Action, Level},
+    resource::{ResourceLocation, Source, fetch_resource, latest_commit_hash, snapshot},
+    settings::{InstallTo, SETTINGS},
+    sync,
+    texmf::{self, Resolution},
 };
 // use super::{config, file::LocalResource, resource::fetch_resource};
-use dialoguer::Confirm;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{
-    fs,
+    fs, io,
     path::{Path, PathBuf},
 };
 
 lazy_static! {
-    pub static ref DOCUMENT_CLASS_RE: Regex =
-        Regex::new(r"(?<documentclass>\\documentclass(\[(?<opts>.+)\])?\{(?<class>\w+)\})")
+    pub(crate) static ref DOCUMENT_CLASS_RE: Regex =
+        Regex::new(r"(?<documentclass>\\documentclass(\[(?<opts>.+)\])?\{(?<class>\w+)\}(?:[ \t]*\[(?<date>[^\]]*)\])?)")
             .unwrap();
+    pub(crate) static ref CLASS_VERSION_RE: Regex = Regex::new(
+        r"%\s*class version (?P<commit>[0-9a-f]+)(?P<dirty>-dirty)?(?:\s*\((?P<provides>[^)]*)\))?"
+    )
+    .unwrap();
 }
 
 #[derive(Clone)]
-pub struct LocalTemplate<'a> {
-    pub template_path: String,
+pub(crate) struct LocalTemplate<'a> {
+    /// Template in the resource repository; if not given, a minimal document
+    /// using the class is written instead
+    pub template_path: Option<String>,
     pub out_dir: &'a String,
     pub out_file: &'a String,
 }
 
 #[derive(Clone)]
-pub struct LocalResource<'a> {
+pub(crate) struct LocalResource<'a> {
     pub resource_path: String,
     pub resource_location: &'a ResourceLocation,
+    pub install_target: &'a InstallTarget,
     pub template: Option<LocalTemplate<'a>>,
 }
 
+/// Where resources are installed: either the local texmf directory (laid out
+/// according to the source of the resources), or a directory within the
+/// project
+pub enum InstallTarget {
+    Texmf(Source),
+    Project(PathBuf),
+}
+
+impl InstallTarget {
+    /// Where to install resources from the given location for a project in the
+    /// given directory
+    pub fn new(install_to: InstallTo, project_root: &Path, loc: &ResourceLocation) -> Self {
+        match install_to {
+            InstallTo::Texmf => InstallTarget::Texmf(Source::of(loc)),
+            InstallTo::Project => {
+                InstallTarget::Project(project_root.join(&SETTINGS.install.project_dir))
+            }
+        }
+    }
+
+    /// Directory that resources are installed into.  Fails if it is the
+    /// local texmf directory, and that cannot be found
+    pub fn resources_dir(&self) -> Result<PathBuf, String> {
+        match self {
+            InstallTarget::Texmf(source) => texmf::texmf_local_resources(source),
+            InstallTarget::Project(dir) => Ok(dir.clone()),
+        }
+    }
+
+    fn contains(&self, resource: &PathBuf) -> Result<bool, String> {
+        match self {
+            InstallTarget::Texmf(source) => texmf::resource_in_local_texmf(resource, source),
+            InstallTarget::Project(dir) => Ok(dir.join(resource).as_path().exists()),
+        }
+    }
+
+    /// Path of a resource relative to the directory it is installed into
+    pub fn installed_name(&self, resource_path: &str) -> PathBuf {
+        let file_name = Path::new(resource_path);
+        let file_name = file_name
+            .strip_prefix(format!(
+                "{}/{}/",
+                config::GITHUB_USER,
+                config::GITHUB_REPO_NAME
+            ))
+            .unwrap_or(file_name);
+        let strip_prefix = match self {
+            InstallTarget::Texmf(source) => {
+                SETTINGS.layout.strip_prefix(&source.user, &source.repo)
+            }
+            InstallTarget::Project(_) => &SETTINGS.layout.strip_prefix,
+        };
+        file_name
+            .strip_prefix(strip_prefix)
+            .unwrap_or(file_name)
+            .to_path_buf()
+    }
+}
+
+/// Location of a resource in the local texmf directory, and whether it was
+/// written on this run
+pub struct InstalledResource {
+    pub local_path: PathBuf,
+    pub written: bool,
+}
+
 impl LocalTemplate<'_> {
     fn out_file(&self) -> PathBuf {
         let mut path = PathBuf::from(&self.out_dir);
@@ -40,136 +117,635 @@ impl LocalTemplate<'_> {
     }
 }
 
-fn write_template(file: LocalResource, dry_run: bool) {
+/// Ask whether to overwrite a document that already exists.  Gives true if it
+/// does not exist, or the user agrees
+pub(crate) fn confirm_overwrite(out_file: &Path) -> bool {
+    if !out_file.exists() {
+        return true;
+    }
+
+    report::action(
+        Level::Debug,
+        Action::Prompt,
+        out_file.display(),
+        format!("Asking whether to overwrite {:?}", out_file),
+    );
+    if prompt::confirm(
+        &format!(
+            "{:?} already exists.  Would you like to overwrite it?",
+            out_file
+        ),
+        false,
+    ) {
+        return true;
+    }
+
+    report::action(
+        Level::Warn,
+        Action::Skip,
+        out_file.display(),
+        format!("File {:?} already exists", out_file),
+    );
+    false
+}
+
+/// Ask whether to replace an installed resource that is out of sync with the
+/// resource repository
+pub(crate) fn confirm_update(local_path: &Path) -> bool {
+    report::action(
+        Level::Warn,
+        Action::SyncMismatch,
+        local_path.display(),
+        format!(
+            "Local resource exists but is out of sync with remote ({:?})",
+            local_path
+        ),
+    );
+    report::action(
+        Level::Debug,
+        Action::Prompt,
+        local_path.display(),
+        format!("Asking whether to update {:?}", local_path),
+    );
+    if prompt::confirm(
+        &format!(
+            "Would you like to update the local resource at {:?}?",
+            local_path
+        ),
+        false,
+    ) {
+        return true;
+    }
+
+    report::action(
+        Level::Info,
+        Action::Skip,
+        local_path.display(),
+        "Ignoring out-of-sync local file",
+    );
+    false
+}
+
+// Write the template for a class to the output directory, unless the user
+// would rather keep a document that is already there
+fn write_template(file: LocalResource, cls_contents: &str) -> Result<(), String> {
     let template = file.template.unwrap();
+    let out_file = template.out_file();
+    if !confirm_overwrite(&out_file) {
+        return Ok(());
+    }
+
+    write_stamped_template(
+        template.template_path.as_deref(),
+        &file.resource_path,
+        cls_contents,
+        file.resource_location,
+        &out_file,
+    )
+}
 
-    // Make template in target dir
+// Plan to write the template for a class.  The class version it is stamped
+// with is only worked out when the plan is applied, so that planning does not
+// need the class itself
+fn plan_template(file: &LocalResource) -> Result<(), String> {
+    let template = file.template.as_ref().unwrap();
     let out_file = template.out_file();
+    plan::record(Step::WriteTemplate {
+        template: template.template_path.clone(),
+        class_resource: file.resource_path.clone(),
+        location: *file.resource_location,
+        commit: snapshot(file.resource_location)?,
+        overwrite: out_file.exists(),
+        path: out_file,
+    });
+    Ok(())
+}
 
-    // Check that we are not overwriting a file!
-    if out_file.exists()
-        && !dry_run
-        && !Confirm::new()
-            .with_prompt(format!(
-                "{:?} already exists.  Would you like to overwrite it?",
-                &out_file
-            ))
-            .interact()
-            .unwrap()
+/// Write a document from a template (or a minimal document using the class,
+/// if no template is given), stamped with the version of the class, whose
+/// contents are given
+pub(crate) fn write_stamped_template(
+    template_path: Option<&str>,
+    class_resource: &str,
+    cls_contents: &str,
+    loc: &ResourceLocation,
+    out_file: &Path,
+) -> Result<(), String> {
+    let class_name = Path::new(class_resource)
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let template_name = template_path
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("skeleton for class {}", class_name));
+
+    let cls_provides = provides::parse(cls_contents);
+    let version = class_version(latest_commit_hash(loc)?, cls_provides.as_ref());
+    let tmpl_contents = template_contents(template_path, &class_name, loc)?;
+
+    // Check that the class we are installing is new enough for the template
+    // (before stamping it, in case the stamp gets in the way)
+    if let Some(cls_provides) = &cls_provides
+        && let Some((class, date)) = provides::newer_date_requested(&tmpl_contents, cls_provides)
     {
-        println!("[WARN] File {:?} already exists", &out_file);
-        return;
+        report::warn(format!(
+            "Template requests class {} from {} or later, but the installed version is from {}",
+            class,
+            date,
+            cls_provides.date.as_deref().unwrap_or("an unknown date")
+        ));
     }
+    let tmpl_contents = stamp_class_version(&tmpl_contents, &version);
 
-    if dry_run {
-        println!(
-            "[INFO] Would have written template {:?} to {:?}",
-            &template.template_path, &out_file
-        );
-    } else {
-        // Write the template file to the specified directory
-        let tmpl_contents = fetch_resource(template.template_path.as_str(), file.resource_location);
-        let tmpl_contents = add_template_resource_version(tmpl_contents, file.resource_location);
+    report::action(
+        Level::Info,
+        Action::Write,
+        out_file.display(),
+        format!("Writing template {:?} to {:?}", &template_name, out_file),
+    );
+    fs::write(out_file, tmpl_contents).map_err(|e| format!("Cannot write {:?}: {}", out_file, e))
+}
 
-        println!(
-            "[INFO] Writing template {:?} to {:?}",
-            &template.template_path, &out_file
-        );
-        fs::write(out_file, tmpl_contents).unwrap();
+// A template as it is in the resource repository, before it is stamped
+fn template_contents(
+    template_path: Option<&str>,
+    class_name: &str,
+    loc: &ResourceLocation,
+) -> Result<String, String> {
+    match template_path {
+        Some(template_path) => fetch_resource(template_path, loc),
+        None => Ok(skeleton_document(class_name)),
     }
 }
 
-pub fn write_resource(file: LocalResource, dry_run: bool) {
-    let file_name = Path::new(&file.resource_path);
-    let file_name = file_name
-        .strip_prefix(format!(
-            "{}/{}/",
-            config::GITHUB_USER,
-            config::GITHUB_REPO_NAME
-        ))
-        .unwrap_or(file_name)
-        .strip_prefix(config::RESOURCE_PARENT)
-        .unwrap_or(file_name)
-        .to_path_buf();
+// Minimal document for classes without a template
+fn skeleton_document(class_name: &str) -> String {
+    format!(
+        "\\documentclass{{{}}}\n\n\\begin{{document}}\n\n\\end{{document}}\n",
+        class_name
+    )
+}
+
+/// Write a resource to the local texmf directory (or project directory)
+pub(crate) fn write_resource(
+    file: LocalResource,
+    dry_run: bool,
+) -> Result<InstalledResource, String> {
+    let file_name = file.install_target.installed_name(&file.resource_path);
+
+    // Move the resource out of the old install layout if necessary
+    let mut written = false;
+    let mut move_planned = false;
+    if let InstallTarget::Texmf(source) = file.install_target {
+        let moved = texmf::migrate_legacy_resource(&file_name, source, dry_run);
+        written |= moved;
+        move_planned = moved && dry_run;
+    }
 
     // Ensure parent path exists
-    let mut local_path = texmf::texmf_local_resources();
+    let mut local_path = file.install_target.resources_dir()?;
     let file_parent = &file_name.parent();
     if let Some(file_parent) = file_parent {
         local_path.push(file_parent)
     }
     if !local_path.exists() {
         if dry_run {
-            println!("[INFO] Would have created the directory {:?}", &local_path);
+            plan::record(Step::CreateDir {
+                path: local_path.clone(),
+            });
         } else {
-            println!("[INFO] Creating directory {:?}", &local_path);
-            fs::create_dir_all(&local_path).unwrap();
+            report::action(
+                Level::Info,
+                Action::Write,
+                local_path.display(),
+                format!("Creating directory {:?}", &local_path),
+            );
+            fs::create_dir_all(&local_path)
+                .map_err(|e| format!("Cannot create directory {:?}: {}", &local_path, e))?;
         }
     }
 
     // Append file name to local resource path
     local_path.push(file_name.file_name().unwrap());
 
+    // Plan by name only: resources are fetched when the plan is applied (and
+    // whether an installed one is out of sync is checked then, too)
+    if dry_run {
+        if !move_planned {
+            let resource = file.resource_path.clone();
+            let location = *file.resource_location;
+            let commit = snapshot(file.resource_location)?;
+            let path = local_path.clone();
+            plan::record(if file.install_target.contains(&file_name)? {
+                Step::UpdateResource {
+                    resource,
+                    location,
+                    commit,
+                    path,
+                    ask: true,
+                }
+            } else {
+                Step::WriteResource {
+                    resource,
+                    location,
+                    commit,
+                    path,
+                }
+            });
+        }
+        // So that the filename database would be refreshed
+        written = true;
+
+        if file.template.is_some() {
+            plan_template(&file)?;
+        }
+        return Ok(InstalledResource {
+            local_path,
+            written,
+        });
+    }
+
     // Write file to local texmf directory
-    let contents = fetch_resource(file.resource_path.as_str(), file.resource_location);
+    let contents = fetch_resource(file.resource_path.as_str(), file.resource_location)?;
+    let write = |contents: &str| {
+        fs::write(&local_path, contents)
+            .map_err(|e| format!("Cannot write {:?}: {}", &local_path, e))
+    };
 
     // Need to move file to local texmf if possible
-    if !texmf::resource_in_local_texmf(&file_name) {
-        if dry_run {
-            println!(
-                "[INFO] Would have written resource {:?} to {:?}",
-                &file_name, &local_path
-            );
-        } else {
-            println!(
-                "[INFO] Writing resource {:?} to {:?}",
-                &file_name, &local_path
-            );
-            fs::write(&local_path, &contents).unwrap();
-        }
+    if !move_planned && !file.install_target.contains(&file_name)? {
+        report::action(
+            Level::Info,
+            Action::Write,
+            local_path.display(),
+            format!("Writing resource {:?} to {:?}", &file_name, &local_path),
+        );
+        write(&contents)?;
+        written = true;
     }
 
     // If local (texmf) resource is not in sync with remote, ask user if we should update local
-    if !sync::check_resource(&local_path, &contents) {
-        println!(
-            "[WARN] Local resource exists but is out of sync with remote ({:?})",
-            file_name
+    if written {
+        // Just written, so in sync
+    } else if sync::check_resource(&local_path, &contents) {
+        report::action(
+            Level::Debug,
+            Action::Skip,
+            local_path.display(),
+            format!("Local resource {:?} is in sync", &file_name),
         );
-        if !dry_run {
-            if Confirm::new() //::with_theme(&dialoguer::theme::ColorfulTheme::default())
-                .with_prompt(format!(
-                    "Would you like to update the local resource at {:?}?",
-                    &local_path
-                ))
-                .interact()
-                .unwrap()
-            {
-                println!(
-                    "[INFO] Updating local resource {:?} at {:?}",
-                    &file_name, &local_path
-                );
-                fs::write(&local_path, &contents).unwrap();
-            } else {
-                println!("[INFO] Ignoring out-of-sync local file");
+    } else if confirm_update(&local_path) {
+        report::action(
+            Level::Info,
+            Action::Write,
+            local_path.display(),
+            format!(
+                "Updating local resource {:?} at {:?}",
+                &file_name, &local_path
+            ),
+        );
+        write(&contents)?;
+        written = true;
+    }
+
+    if file.template.is_some() {
+        write_template(file, &contents)?;
+    }
+
+    Ok(InstalledResource {
+        local_path,
+        written,
+    })
+}
+
+/// Update an already-installed resource without prompting, if it has changed.
+/// Gives true if the installed copy was updated
+pub(crate) fn refresh_resource(file: &LocalResource, dry_run: bool) -> Result<bool, String> {
+    let file_name = file.install_target.installed_name(&file.resource_path);
+    let local_path = file.install_target.resources_dir()?.join(&file_name);
+    if !local_path.exists() {
+        return Ok(false);
+    }
+
+    // Whether it is out of sync is checked when the plan is applied
+    if dry_run {
+        plan::record(Step::UpdateResource {
+            resource: file.resource_path.clone(),
+            location: *file.resource_location,
+            commit: snapshot(file.resource_location)?,
+            path: local_path,
+            ask: false,
+        });
+        return Ok(true);
+    }
+
+    let contents = fetch_resource(file.resource_path.as_str(), file.resource_location)?;
+    if sync::check_resource(&local_path, &contents) {
+        return Ok(false);
+    }
+
+    report::action(
+        Level::Info,
+        Action::Write,
+        local_path.display(),
+        format!(
+            "Updating local resource {:?} at {:?}",
+            &file_name, &local_path
+        ),
+    );
+    fs::write(&local_path, &contents)
+        .map_err(|e| format!("Cannot write {:?}: {}", &local_path, e))?;
+    Ok(true)
+}
+
+// Whether to refresh the filename database after installing: only if
+// something was written into the texmf tree, and the user has not opted out
+fn refresh_wanted(installed: &[InstalledResource], no_mktexlsr: bool) -> bool {
+    !no_mktexlsr && installed.iter().any(|resource| resource.written)
+}
+
+/// Make installed resources visible to TeX: for resources installed into a
+/// project, write a latexmkrc; otherwise, refresh the filename database
+pub fn finish_install(
+    installed: &[InstalledResource],
+    install_target: &InstallTarget,
+    out_dir: &String,
+    no_mktexlsr: bool,
+    dry_run: bool,
+) -> Result<(), String> {
+    // Make sure the document can find resources installed into the project
+    if let InstallTarget::Project(resources_dir) = install_target {
+        return write_latexmkrc(out_dir, resources_dir, dry_run);
+    }
+
+    // Refresh the filename database so that TeX can find the new resources
+    if refresh_wanted(installed, no_mktexlsr) {
+        let texmf_home = texmf::texmf().ok_or("Cannot find the local texmf directory")?;
+        if dry_run {
+            if texmf::has_ls_r(&texmf_home) {
+                plan::record(Step::RefreshDatabase { tree: texmf_home });
+            }
+        } else if texmf::has_ls_r(&texmf_home) {
+            report::info(format!("Refreshing filename database in {:?}", &texmf_home));
+            if !texmf::refresh_filename_database(&texmf_home) {
+                report::warn(format!(
+                    "Could not refresh filename database; run `mktexlsr {}` manually",
+                    texmf_home.display()
+                ));
             }
         }
     }
 
-    if file.template.is_some() {
-        write_template(file, dry_run);
+    // Warn if TeX would find a different copy of anything we installed (or
+    // none at all)
+    if !dry_run {
+        for resource in installed {
+            match texmf::resolve_installed(&resource.local_path) {
+                Resolution::Installed => {}
+                Resolution::Shadowed(copies) => {
+                    report::warn(format!(
+                        "{:?} is shadowed by another file of the same name; TeX will use {:?}",
+                        &resource.local_path, &copies[0]
+                    ));
+                    for copy in copies {
+                        report::warn(format!("    {:?}", copy));
+                    }
+                }
+                Resolution::NotFound => report::warn(format!(
+                    "TeX cannot find {:?}; if the texmf tree has a filename database, run `mktexlsr`",
+                    &resource.local_path
+                )),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write a .latexmkrc to the output directory so that latexmk can find
+/// resources installed into the project rather than the local texmf directory.
+/// If there already is one (e.g., from the project scaffold), the search path
+/// is added to it
+pub(crate) fn write_latexmkrc(
+    out_dir: &String,
+    resources_dir: &Path,
+    dry_run: bool,
+) -> Result<(), String> {
+    let latexmkrc = Path::new(out_dir).join(config::LATEXMKRC);
+    let search_path = texinputs_path(Path::new(out_dir), resources_dir);
+    let line = format!("ensure_path('TEXINPUTS', '{}');", search_path);
+
+    if dry_run {
+        plan::record(Step::WriteLatexmkrc {
+            path: latexmkrc,
+            line,
+        });
+    } else {
+        add_to_latexmkrc(&latexmkrc, &line)
+            .map_err(|e| format!("Could not write {:?}: {}", &latexmkrc, e))?;
     }
+    report::info(format!(
+        "To compile without latexmk, set TEXINPUTS={}: in your environment",
+        search_path
+    ));
+    Ok(())
 }
 
-fn add_template_resource_version(tmpl_contents: String, loc: &ResourceLocation) -> String {
-    let commit_hash = match loc {
-        ResourceLocation::Local => local::latest_local_commit_hash(),
-        ResourceLocation::Remote => remote::latest_commit_hash(),
+/// Add a line to a latexmkrc (if it does not already have it), creating the
+/// file if needed
+pub(crate) fn add_to_latexmkrc(latexmkrc: &Path, line: &str) -> Result<(), String> {
+    let existing = match fs::read_to_string(latexmkrc) {
+        Ok(contents) => Some(contents),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.to_string()),
+    };
+    let Some(contents) = merge_latexmkrc(existing.as_deref(), line) else {
+        return Ok(());
     };
 
+    report::action(
+        Level::Info,
+        Action::Write,
+        latexmkrc.display(),
+        match existing {
+            Some(_) => format!("Adding project resources to {:?}", latexmkrc),
+            None => format!("Writing {:?}", latexmkrc),
+        },
+    );
+    fs::write(latexmkrc, contents).map_err(|e| e.to_string())
+}
+
+// Contents of a latexmkrc with the given line, or None if it already has it
+fn merge_latexmkrc(existing: Option<&str>, line: &str) -> Option<String> {
+    let mut contents = existing.unwrap_or_default().to_string();
+    if contents
+        .lines()
+        .any(|existing_line| existing_line.trim() == line)
+    {
+        return None;
+    }
+
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str("# Find classes installed into the project by mktex\n");
+    contents.push_str(line);
+    contents.push('\n');
+    Some(contents)
+}
+
+// Search path for resources installed into the project, relative to the
+// document's directory if they are within it
+fn texinputs_path(out_dir: &Path, resources_dir: &Path) -> String {
+    // The trailing double slash tells kpathsea to search subdirectories too
+    match resources_dir.strip_prefix(out_dir) {
+        Ok(resources_dir) => format!("./{}//", resources_dir.display()),
+        Err(_) => format!("{}//", resources_dir.display()),
+    }
+}
+
+/// Version of a class as recorded in documents: the commit of the resource
+/// repository, along with the class's own idea of its version, if it declares
+/// one
+pub(crate) fn class_version(
+    commit_hash: String,
+    cls_provides: Option<&provides::Provides>,
+) -> String {
+    match cls_provides {
+        Some(cls_provides) => format!("{} ({})", commit_hash, cls_provides),
+        None => commit_hash,
+    }
+}
+
+/// Record the version of a document's class next to its \documentclass line
+/// (after the release date it requests, if any)
+pub(crate) fn stamp_class_version(contents: &str, version: &str) -> String {
     DOCUMENT_CLASS_RE
         .replace(
-            &tmpl_contents,
-            format!("$documentclass  % class version {}", commit_hash),
+            contents,
+            format!("$documentclass  % class version {}", version),
         )
         .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "ensure_path('TEXINPUTS', './tex//');";
+
+    #[test]
+    fn stamp_after_class() {
+        let contents = stamp_class_version("\\documentclass[11pt]{arteacle}\n", "1d72b1d");
+        assert_eq!(
+            contents,
+            "\\documentclass[11pt]{arteacle}  % class version 1d72b1d\n"
+        );
+    }
+
+    #[test]
+    fn stamp_after_requested_date() {
+        let contents = stamp_class_version(
+            "\\documentclass{arteacle}[2024/03/01]\n",
+            "1d72b1d (arteacle 2024/03/01 v1.4)",
+        );
+        assert_eq!(
+            contents,
+            "\\documentclass{arteacle}[2024/03/01]  % class version 1d72b1d (arteacle 2024/03/01 v1.4)\n"
+        );
+        let class = provides::parse("\\ProvidesClass{arteacle}[2024/01/01 v1.3]").unwrap();
+        assert!(provides::newer_date_requested(&contents, &class).is_some());
+    }
+
+    #[test]
+    fn skeleton_for_class_without_template() {
+        let contents = stamp_class_version(&skeleton_document("thesis"), "1d72b1d");
+        assert!(contents.starts_with("\\documentclass{thesis}  % class version 1d72b1d\n"));
+        assert!(contents.contains("\\begin{document}"));
+        assert!(contents.ends_with("\\end{document}\n"));
+    }
+
+    #[test]
+    fn class_version_parsed() {
+        let caps = CLASS_VERSION_RE
+            .captures(
+                "\\documentclass{arteacle}  % class version 1d72b1d (arteacle 2024/03/01 v1.4)",
+            )
+            .unwrap();
+        assert_eq!(&caps["commit"], "1d72b1d");
+        assert!(caps.name("dirty").is_none());
+        assert_eq!(&caps["provides"], "arteacle 2024/03/01 v1.4");
+    }
+
+    #[test]
+    fn class_version_dirty() {
+        let caps = CLASS_VERSION_RE
+            .captures("\\documentclass{arteacle}  % class version 1d72b1d-dirty")
+            .unwrap();
+        assert_eq!(&caps["commit"], "1d72b1d");
+        assert!(caps.name("dirty").is_some());
+        assert!(caps.name("provides").is_none());
+    }
+
+    #[test]
+    fn latexmkrc_created() {
+        let contents = merge_latexmkrc(None, LINE).unwrap();
+        assert!(contents.ends_with(&format!("{}\n", LINE)));
+    }
+
+    #[test]
+    fn latexmkrc_merged_into_existing() {
+        let contents = merge_latexmkrc(Some("$pdf_mode = 1;"), LINE).unwrap();
+        assert!(contents.starts_with("$pdf_mode = 1;\n"));
+        assert!(contents.contains(LINE));
+    }
+
+    #[test]
+    fn latexmkrc_left_alone_if_it_has_line() {
+        let existing = format!("$pdf_mode = 1;\n{}\n", LINE);
+        assert_eq!(merge_latexmkrc(Some(&existing), LINE), None);
+    }
+
+    fn installed(written: bool) -> InstalledResource {
+        InstalledResource {
+            local_path: PathBuf::from("/texmf/tex/latex/local/arteacle.cls"),
+            written,
+        }
+    }
+
+    #[test]
+    fn refresh_after_writing() {
+        assert!(refresh_wanted(&[installed(false), installed(true)], false));
+    }
+
+    #[test]
+    fn no_refresh_if_nothing_written() {
+        assert!(!refresh_wanted(&[installed(false)], false));
+        assert!(!refresh_wanted(&[], false));
+    }
+
+    #[test]
+    fn no_refresh_if_opted_out() {
+        assert!(!refresh_wanted(&[installed(true)], true));
+    }
+
+    #[test]
+    fn texinputs_relative_to_document() {
+        assert_eq!(
+            texinputs_path(Path::new("."), Path::new("./tex")),
+            "./tex//"
+        );
+        assert_eq!(
+            texinputs_path(Path::new("doc"), Path::new("doc/tex")),
+            "./tex//"
+        );
+    }
+
+    #[test]
+    fn texinputs_absolute_project_dir() {
+        assert_eq!(
+            texinputs_path(Path::new("."), Path::new("/srv/tex")),
+            "/srv/tex//"
+        );
+    }
+}
@@ -1,32 +1,35 @@
 use super::{
     config,
     input::INPUT_RE,
-    remote,
     resource::{self, ResourceLocation},
 };
 use chrono::prelude::*;
-use regex::Captures;
 
-pub fn expand_input_paths(contents_raw: String, loc: &ResourceLocation) -> String {
+/// Replace each `\input` in a class with the contents of the file it inputs,
+/// so that the class can be used without the resource repository
+pub fn expand_input_paths(contents_raw: String, loc: &ResourceLocation) -> Result<String, String> {
     // We want to expand/evaluate lines in LaTeX like `\input{...}`
-    let expanded = INPUT_RE
-        .replace_all(&contents_raw, |caps: &Captures| {
-            let input_path = caps.name("path").unwrap().as_str();
-            fetch_resource(input_path, loc)
-        })
-        .to_string();
+    let mut expanded = String::new();
+    let mut last = 0;
+    for caps in INPUT_RE.captures_iter(&contents_raw) {
+        let input = caps.get(0).unwrap();
+        expanded.push_str(&contents_raw[last..input.start()]);
+        expanded.push_str(&fetch_resource(&caps["path"], loc)?);
+        last = input.end();
+    }
+    expanded.push_str(&contents_raw[last..]);
     add_version_metadata(expanded, loc)
 }
 
-fn fetch_resource(input_path: &str, loc: &ResourceLocation) -> String {
+fn fetch_resource(input_path: &str, loc: &ResourceLocation) -> Result<String, String> {
     let resource_path = input_path
-        .split(config::GITHUB_REPO_NAME)
-        .last()
-        .expect("Cannot find tex-macros/ repo in input path");
+        .rsplit(config::GITHUB_REPO_NAME)
+        .next()
+        .unwrap_or(input_path);
     resource::fetch_resource(resource_path, loc)
 }
 
-fn add_version_metadata(contents_raw: String, loc: &ResourceLocation) -> String {
+fn add_version_metadata(contents_raw: String, loc: &ResourceLocation) -> Result<String, String> {
     let local_dt: DateTime<Local> = Local::now();
     let formatted_date = local_dt.format("%I:%M %p on %A, %e %B, %Y %Z").to_string();
 
@@ -34,12 +37,12 @@ fn add_version_metadata(contents_raw: String, loc: &ResourceLocation) -> String
     contents.push_str(format!("% Frozen version at {}\n\n", formatted_date).as_str());
 
     if loc == &ResourceLocation::Remote {
-        let latest_commit = remote::latest_commit_hash();
+        let latest_commit = resource::latest_commit_hash(loc)?;
         contents.pop(); // Remove other new line if remote info added
         contents.push_str(format!("% At commit version {} \n\n", latest_commit).as_str());
     }
 
     contents.push_str(&contents_raw);
 
-    contents
+    Ok(contents)
 }
@@ -0,0 +1,279 @@
+// Inspect an existing document
+//
+// Templates written by mktex are stamped with the commit of the resource
+// repository that their class was installed from:
+//
+//     \documentclass{arteacle}  % class version 1a2b3c4 (arteacle 2024/03/01 v1.4)
+//
+// From this we can tell whether the installed class is still the one the
+// document was made with, and what has changed upstream since.
+
+use super::{
+    config,
+    file::{CLASS_VERSION_RE, DOCUMENT_CLASS_RE, InstallTarget},
+    kind, local, provides, remote, report,
+    resource::{
+        ResourceLocation, fetch_resource_at, latest_commit_hash, snapshot, try_fetch_resource,
+    },
+    sync,
+};
+use serde::Serialize;
+use std::{fs, path::Path};
+
+#[derive(Serialize)]
+pub struct UpstreamCommit {
+    pub commit: String,
+    pub summary: String,
+}
+
+#[derive(Serialize)]
+pub struct DocumentInfo {
+    pub class: String,
+    pub class_resource: String,
+    /// Commit of the resource repository recorded in the document
+    pub commit: Option<String>,
+    /// Class metadata recorded in the document, from its \ProvidesClass line
+    pub recorded_version: Option<String>,
+    pub installed_path: Option<String>,
+    pub installed_version: Option<String>,
+    /// Whether the installed class is the one from the recorded commit
+    pub installed_matches_commit: Option<bool>,
+    pub latest_commit: Option<String>,
+    /// Commits to the resource repository since the recorded commit
+    pub upstream_commits: Vec<UpstreamCommit>,
+    /// Files changed in the resource repository since the recorded commit
+    pub upstream_changes: Vec<String>,
+}
+
+// Commits and changed files between a given commit and the commit that
+// resources are read at
+fn changes_since(commit: &str, loc: &ResourceLocation) -> Option<remote::Changes> {
+    let commit = commit.strip_suffix(config::DIRTY_SUFFIX).unwrap_or(commit);
+    let changes = snapshot(loc).and_then(|head| {
+        let head = head.strip_suffix(config::DIRTY_SUFFIX).unwrap_or(&head);
+        match loc {
+            ResourceLocation::Local => local::local_changes_since(commit, head),
+            ResourceLocation::Remote => remote::compare(commit, head),
+        }
+    });
+    match changes {
+        Ok(changes) => Some(changes),
+        Err(e) => {
+            report::warn(format!("Could not compare with upstream: {}", e));
+            None
+        }
+    }
+}
+
+// What a document records about its class: its name, and (if the document is
+// stamped) the commit it was installed from and its version
+struct Recorded {
+    class: String,
+    /// Including whether the document was made from uncommitted changes
+    commit: Option<String>,
+    version: Option<String>,
+}
+
+fn recorded(contents: &str) -> Option<Recorded> {
+    let class = DOCUMENT_CLASS_RE.captures(contents)?["class"]
+        .trim()
+        .to_string();
+    let stamp = CLASS_VERSION_RE.captures(contents);
+    let commit = stamp.as_ref().map(|caps| {
+        let dirty = caps.name("dirty").map_or("", |dirty| dirty.as_str());
+        format!("{}{}", &caps["commit"], dirty)
+    });
+    let version = stamp
+        .as_ref()
+        .and_then(|caps| caps.name("provides"))
+        .map(|provides| provides.as_str().to_string());
+
+    Some(Recorded {
+        class,
+        commit,
+        version,
+    })
+}
+
+/// Gather information about a document and the class it was made with
+pub fn document_info(
+    doc: &Path,
+    loc: &ResourceLocation,
+    install_target: &InstallTarget,
+) -> Result<DocumentInfo, String> {
+    let contents =
+        fs::read_to_string(doc).map_err(|e| format!("Could not read {:?}: {}", doc, e))?;
+    let Recorded {
+        class,
+        commit,
+        version: recorded_version,
+    } = recorded(&contents).ok_or_else(|| format!("No \\documentclass found in {:?}", doc))?;
+    let class_resource = kind::class_resource_named(&class);
+
+    let installed_path = install_target
+        .resources_dir()?
+        .join(install_target.installed_name(&class_resource));
+    let installed_contents = fs::read_to_string(&installed_path).ok();
+    let installed_version = installed_contents
+        .as_deref()
+        .and_then(provides::parse)
+        .map(|provides| provides.to_string());
+
+    let installed_matches_commit = match (&commit, &installed_contents) {
+        (Some(commit), Some(_)) => fetch_resource_at(&class_resource, commit, loc)
+            .map(|recorded| sync::check_resource(&installed_path, &recorded)),
+        _ => None,
+    };
+
+    let latest_commit = match loc {
+        ResourceLocation::Local => Some(latest_commit_hash(loc)?),
+        ResourceLocation::Remote => {
+            // Only ask for the latest commit if the repository is reachable
+            match try_fetch_resource(&class_resource, loc) {
+                Ok(Some(_)) => Some(latest_commit_hash(loc)?),
+                Ok(None) => None,
+                Err(e) => {
+                    report::warn(format!("Could not reach the resource repository: {}", e));
+                    None
+                }
+            }
+        }
+    };
+
+    let (upstream_commits, upstream_changes) = match &commit {
+        Some(commit) if latest_commit.as_deref() != Some(commit.as_str()) => {
+            changes_since(commit, loc).unwrap_or_default()
+        }
+        _ => (Vec::new(), Vec::new()),
+    };
+
+    Ok(DocumentInfo {
+        class,
+        class_resource,
+        commit,
+        recorded_version,
+        installed_path: installed_contents
+            .is_some()
+            .then(|| installed_path.display().to_string()),
+        installed_version,
+        installed_matches_commit,
+        latest_commit,
+        upstream_commits: upstream_commits
+            .into_iter()
+            .map(|(commit, summary)| UpstreamCommit { commit, summary })
+            .collect(),
+        upstream_changes,
+    })
+}
+
+/// Print information about a document, as text or as JSON
+pub fn print_info(
+    doc: &Path,
+    loc: &ResourceLocation,
+    install_target: &InstallTarget,
+    json: bool,
+) -> Result<(), String> {
+    let info = document_info(doc, loc, install_target)?;
+
+    if json {
+        report::output(serde_json::to_string_pretty(&info).unwrap());
+        return Ok(());
+    }
+
+    let or_dash = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+    report::output(format!(
+        "class:      {} ({})",
+        info.class, info.class_resource
+    ));
+    report::output(format!(
+        "recorded:   {}",
+        match (&info.commit, &info.recorded_version) {
+            (Some(commit), Some(version)) => format!("{} ({})", commit, version),
+            (Some(commit), None) => commit.clone(),
+            _ => "- (document has no class version stamp)".to_string(),
+        }
+    ));
+    report::output(format!(
+        "installed:  {}{}",
+        or_dash(&info.installed_path),
+        info.installed_version
+            .as_ref()
+            .map(|version| format!(" ({})", version))
+            .unwrap_or_default()
+    ));
+    report::output(format!(
+        "matches:    {}",
+        match info.installed_matches_commit {
+            Some(true) => "installed class is from the recorded commit",
+            Some(false) => "installed class differs from the recorded commit",
+            None => "-",
+        }
+    ));
+    report::output(format!("latest:     {}", or_dash(&info.latest_commit)));
+
+    if !info.upstream_commits.is_empty() {
+        report::output("");
+        report::output(format!(
+            "{} commit(s) upstream since {}:",
+            info.upstream_commits.len(),
+            or_dash(&info.commit)
+        ));
+        for commit in &info.upstream_commits {
+            report::output(format!("    {} {}", commit.commit, commit.summary));
+        }
+    }
+    if !info.upstream_changes.is_empty() {
+        report::output("");
+        report::output("Changed files:");
+        for path in &info.upstream_changes {
+            let marker = if *path == info.class_resource {
+                " (class)"
+            } else {
+                ""
+            };
+            report::output(format!("    {}{}", path, marker));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamped_document() {
+        let recorded = recorded(
+            "\\documentclass[11pt]{arteacle}  % class version 1a2b3c4 (arteacle 2024/03/01 v1.4)\n",
+        )
+        .unwrap();
+        assert_eq!(recorded.class, "arteacle");
+        assert_eq!(recorded.commit.as_deref(), Some("1a2b3c4"));
+        assert_eq!(
+            recorded.version.as_deref(),
+            Some("arteacle 2024/03/01 v1.4")
+        );
+    }
+
+    #[test]
+    fn dirty_stamp_kept() {
+        let recorded =
+            recorded("\\documentclass{lettear}  % class version 1a2b3c4-dirty\n").unwrap();
+        assert_eq!(recorded.commit.as_deref(), Some("1a2b3c4-dirty"));
+        assert_eq!(recorded.version, None);
+    }
+
+    #[test]
+    fn unstamped_document() {
+        let recorded = recorded("\\documentclass{article}\n\\begin{document}\n").unwrap();
+        assert_eq!(recorded.class, "article");
+        assert_eq!(recorded.commit, None);
+        assert_eq!(recorded.version, None);
+    }
+
+    #[test]
+    fn not_a_document() {
+        assert!(recorded("\\input{chapter}\n").is_none());
+    }
+}
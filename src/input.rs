@@ -14,10 +14,10 @@ lazy_static! {
     .unwrap();
 }
 
-pub fn sourced_files(cls: LocalResource) -> Vec<LocalResource> {
-    let contents = fetch_resource(cls.resource_path.as_str(), cls.resource_location);
+pub fn sourced_files(cls: LocalResource) -> Result<Vec<LocalResource>, String> {
+    let contents = fetch_resource(cls.resource_path.as_str(), cls.resource_location)?;
 
-    INPUT_RE
+    Ok(INPUT_RE
         .captures_iter(&contents)
         .map(|caps: Captures| LocalResource {
             resource_path: format!(
@@ -27,7 +27,8 @@ pub fn sourced_files(cls: LocalResource) -> Vec<LocalResource> {
                 caps.name("path").unwrap().as_str()
             ),
             resource_location: cls.resource_location,
+            install_target: cls.install_target,
             template: None,
         })
-        .collect()
+        .collect())
 }
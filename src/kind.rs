@@ -0,0 +1,236 @@
+// Kinds of document that mktex can make, and the resources each one needs
+
+use super::{
+    config::*,
+    file::{self, InstallTarget, InstalledResource, LocalResource, LocalTemplate},
+    input, report,
+    resource::{self, ResourceLocation},
+};
+use clap::ValueEnum;
+use std::path::Path;
+
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+pub enum DocumentKind {
+    Article,
+    Letter,
+    FormalLetter,
+    Beamer,
+}
+
+impl DocumentKind {
+    /// Name of the document kind, as given on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            DocumentKind::Article => "article",
+            DocumentKind::Letter => "letter",
+            DocumentKind::FormalLetter => "formal-letter",
+            DocumentKind::Beamer => "beamer",
+        }
+    }
+
+    pub fn class_resource(&self) -> &'static str {
+        match self {
+            DocumentKind::Article => CLS_RESOURCE,
+            DocumentKind::Letter | DocumentKind::FormalLetter => LTR_RESOURCE,
+            DocumentKind::Beamer => BMR_RESOURCE,
+        }
+    }
+
+    pub fn template_resource(&self) -> &'static str {
+        match self {
+            DocumentKind::Article => TMPL_RESOURCE,
+            DocumentKind::Letter => LTR_TMPL_RESOURCE,
+            DocumentKind::FormalLetter => LTR_FML_TMPL_RESOURCE,
+            DocumentKind::Beamer => BMR_TMPL_RESOURCE,
+        }
+    }
+
+    /// Resources required by the class that it does not \input itself
+    pub fn extra_resources(&self) -> Vec<String> {
+        match self {
+            DocumentKind::Beamer => [
+                BMR_THEME_COLOUR,
+                BMR_THEME_INNER,
+                BMR_THEME_OUTER,
+                BMR_THEME_MAIN,
+            ]
+            .into_iter()
+            .map(|file| Path::new(BMR_THEME_PATH).join(file).display().to_string())
+            .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The resource a class comes from: that of the kinds of document using it,
+/// or, for any other class (e.g., one given with --class-file), the class
+/// directory
+pub(crate) fn class_resource_named(class: &str) -> String {
+    DocumentKind::value_variants()
+        .iter()
+        .map(|kind| kind.class_resource())
+        .find(|resource| {
+            Path::new(resource)
+                .file_stem()
+                .is_some_and(|stem| stem == class)
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{}{}.cls", RESOURCE_PARENT, class))
+}
+
+/// Install the class for a kind of document (along with everything it needs),
+/// and write its template to the output directory
+pub fn write_document(
+    kind: DocumentKind,
+    resource_location: &ResourceLocation,
+    install_target: &InstallTarget,
+    out_dir: &String,
+    out_file: &String,
+    dry_run: bool,
+) -> Result<Vec<InstalledResource>, String> {
+    write_class_document(
+        kind.class_resource(),
+        Some(kind.template_resource()),
+        kind.extra_resources(),
+        resource_location,
+        install_target,
+        out_dir,
+        out_file,
+        dry_run,
+    )
+}
+
+/// Install any class from the resource repository (along with everything it
+/// needs), and write a document using it to the output directory.  If no
+/// template is given, a minimal document is written
+#[allow(clippy::too_many_arguments)]
+pub fn write_class_document(
+    class_resource: &str,
+    template_resource: Option<&str>,
+    extra_resources: Vec<String>,
+    resource_location: &ResourceLocation,
+    install_target: &InstallTarget,
+    out_dir: &String,
+    out_file: &String,
+    dry_run: bool,
+) -> Result<Vec<InstalledResource>, String> {
+    let mut installed = Vec::new();
+
+    // Fetch everything the document needs at once, rather than one by one:
+    // first the class, its template, and any extra resources, and then the
+    // files that the class \inputs.  (A dry run only needs the class.)
+    if !dry_run {
+        let mut resources = extra_resources.clone();
+        resources.push(class_resource.to_string());
+        resources.extend(template_resource.map(str::to_string));
+        resource::prefetch(&resources, resource_location)?;
+    }
+
+    let cls = LocalResource {
+        resource_path: class_resource.to_string(),
+        resource_location,
+        install_target,
+        template: Some(LocalTemplate {
+            template_path: template_resource.map(str::to_string),
+            out_dir,
+            out_file,
+        }),
+    };
+    let sourced_files = input::sourced_files(cls.clone())?;
+    if !dry_run {
+        let sourced_paths: Vec<String> = sourced_files
+            .iter()
+            .map(|file| file.resource_path.clone())
+            .collect();
+        resource::prefetch(&sourced_paths, resource_location)?;
+    }
+
+    // Resources such as Beamer theme files
+    for resource_path in extra_resources {
+        let sty = LocalResource {
+            resource_path,
+            resource_location,
+            install_target,
+            template: None,
+        };
+        installed.push(file::write_resource(sty, dry_run)?);
+    }
+
+    // Main class file
+    installed.push(file::write_resource(cls, dry_run)?);
+
+    // Write sourced files required by the class
+    report::info("Checking sync status of local source files...");
+    for source_file in sourced_files {
+        installed.push(file::write_resource(source_file, dry_run)?)
+    }
+    report::info("Done");
+
+    Ok(installed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kind_resources() {
+        let resources = |kind: DocumentKind| (kind.class_resource(), kind.template_resource());
+        assert_eq!(
+            resources(DocumentKind::Article),
+            ("class/arteacle.cls", "templates/arteacle.tex")
+        );
+        assert_eq!(
+            resources(DocumentKind::Letter),
+            ("class/lettear.cls", "templates/letter/lettear.tex")
+        );
+        assert_eq!(
+            resources(DocumentKind::FormalLetter),
+            ("class/lettear.cls", "templates/letter/lettear-formal.tex")
+        );
+        assert_eq!(
+            resources(DocumentKind::Beamer),
+            ("class/teamer.cls", "templates/teamer.tex")
+        );
+    }
+
+    #[test]
+    fn beamer_themes_are_extra_resources() {
+        assert_eq!(
+            DocumentKind::Beamer.extra_resources(),
+            [
+                "class/teamer/beamercolorthemetea.sty",
+                "class/teamer/beamerinnerthemetea.sty",
+                "class/teamer/beamerouterthemetea.sty",
+                "class/teamer/beamerthemetea.sty",
+            ]
+        );
+        for kind in [
+            DocumentKind::Article,
+            DocumentKind::Letter,
+            DocumentKind::FormalLetter,
+        ] {
+            assert!(kind.extra_resources().is_empty());
+        }
+    }
+
+    #[test]
+    fn names_match_command_line() {
+        for kind in DocumentKind::value_variants() {
+            let value = kind.to_possible_value().unwrap();
+            assert_eq!(value.get_name(), kind.name());
+        }
+    }
+
+    #[test]
+    fn class_resource_of_kind() {
+        for kind in DocumentKind::value_variants() {
+            let class = Path::new(kind.class_resource()).file_stem().unwrap();
+            assert_eq!(
+                class_resource_named(&class.to_string_lossy()),
+                kind.class_resource()
+            );
+        }
+        assert_eq!(class_resource_named("thesis"), "class/thesis.cls");
+    }
+}
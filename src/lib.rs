@@ -0,0 +1,51 @@
+//! Make working with personal common LaTeX templates and classes easier.
+//!
+//! Classes, packages, and templates live in a resource repository, fetched
+//! either from GitHub or from a local checkout ([`ResourceLocation`]).  Classes
+//! are installed into the local texmf tree or into the project
+//! ([`InstallTarget`]), and documents are made from their templates
+//! ([`kind::write_document`]).  A class can also be frozen into a single file
+//! with everything it inputs ([`freeze::expand_input_paths`]).
+//!
+//! Functions that can fail give their error as a message (`Result<_, String>`)
+//! rather than exiting.  Progress, and the output of commands such as
+//! [`catalogue::print_catalogue`], is reported through [`report::set_reporter`]
+//! rather than printed, and questions (such as whether to overwrite a file) are
+//! asked through [`prompt::set_prompter`]; by default, every question gets its
+//! default answer.
+
+pub mod bib;
+pub mod build;
+pub mod cache;
+pub mod catalogue;
+pub mod config;
+pub mod doctor;
+pub mod figure;
+pub mod file;
+pub mod freeze;
+pub mod info;
+mod input;
+pub mod kind;
+pub mod local;
+pub mod plan;
+pub mod prompt;
+mod provides;
+mod remote;
+pub mod report;
+pub mod resource;
+pub mod scaffold;
+pub mod settings;
+pub mod status;
+mod sync;
+pub mod texmf;
+pub mod upgrade;
+pub mod watch;
+
+pub use file::{InstallTarget, InstalledResource, finish_install};
+pub use freeze::expand_input_paths;
+pub use kind::{DocumentKind, write_class_document, write_document};
+pub use prompt::{Prompter, set_prompter};
+pub use report::{Event, Level, set_reporter};
+pub use resource::{
+    ResourceLocation, Source, fetch_resource, latest_commit_hash, snapshot, try_fetch_resource,
+};
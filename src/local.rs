@@ -1,51 +1,426 @@
-use super::config;
-use std::path::PathBuf;
-use std::process::{self, Command, Stdio};
+// The local checkout of the resource repository
+//
+// The checkout is at ~/projects/tex-macros unless the settings say otherwise,
+// and may be a bare repository or a git worktree.  Resources are read from its
+// working tree, so that changes can be tried out before they are committed,
+// unless the settings give a ref to read them at (or the repository is bare,
+// and has no working tree), in which case they are read from git's objects.
+//
+// Documents made from a working tree with uncommitted changes are stamped
+// with the commit and a "-dirty" suffix (see resource::snapshot), and a
+// checkout that has fallen behind its upstream branch is offered a pull.
+use super::{
+    config, prompt, remote,
+    report::{self, Action, Level},
+    resource::{self, ResourceLocation},
+    settings::SETTINGS,
+};
+use lazy_static::lazy_static;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+lazy_static! {
+    static ref READS_FROM_OBJECTS: bool = SETTINGS.local.rev.is_some() || is_bare();
+    static ref ORIGIN: Option<(String, String)> = local_resource_dir()
+        .exists()
+        .then(|| git_output(&["remote", "get-url", "origin"]))
+        .flatten()
+        .and_then(|url| parse_remote_url(url.trim()));
+}
+
+/// Get expected local resource path, whether or not it exists
+pub(crate) fn local_resource_dir() -> PathBuf {
+    let home = home::home_dir().expect("Cannot get home directory");
+    match &SETTINGS.local.path {
+        Some(path) => match path.strip_prefix("~") {
+            Ok(path) => home.join(path),
+            Err(_) => path.clone(),
+        },
+        None => home
+            .join(config::RESOURCE_ROOT)
+            .join(config::GITHUB_REPO_NAME),
+    }
+}
 
 /// Get local resource path
-pub fn local_resource_path() -> PathBuf {
-    let resource_dir = home::home_dir()
-        .expect("Cannot get home directory")
-        .join(config::RESOURCE_ROOT)
-        .join(config::GITHUB_REPO_NAME);
+pub(crate) fn local_resource_path() -> Result<PathBuf, String> {
+    let resource_dir = local_resource_dir();
 
     if !resource_dir.as_path().exists() {
-        panic!(
-            "{}",
-            format!(
-                "No local resource path at ~/{}/{}/",
-                config::RESOURCE_ROOT,
-                config::GITHUB_REPO_NAME
-            )
-        )
+        return Err(format!(
+            "No local resource repository at {:?}; clone https://github.com/{}/{} there, or set `path` under [local] in your settings",
+            resource_dir,
+            config::GITHUB_USER,
+            config::GITHUB_REPO_NAME
+        ));
+    }
+
+    Ok(resource_dir)
+}
+
+/// Owner and name of the repository that the local checkout was cloned from,
+/// if it has an origin remote
+pub(crate) fn origin() -> Option<(String, String)> {
+    ORIGIN.clone()
+}
+
+// Owner and name of a repository from its URL, e.g.
+// https://github.com/jakewilliami/tex-macros.git or
+// git@github.com:jakewilliami/tex-macros.git (but not a path on disk, which
+// does not say who owns the repository)
+fn parse_remote_url(url: &str) -> Option<(String, String)> {
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/')?.1,
+        None => {
+            url.split_once(':')
+                .filter(|(host, _)| host.contains('@'))?
+                .1
+        }
+    };
+    let mut components = path
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .rsplit('/');
+    let repo = components.next().filter(|repo| !repo.is_empty())?;
+    let user = components.next().filter(|user| !user.is_empty())?;
+    Some((user.to_string(), repo.to_string()))
+}
+
+/// Ref that resources are read at, if they are read from git's objects
+pub(crate) fn source_rev() -> &'static str {
+    SETTINGS.local.rev.as_deref().unwrap_or("HEAD")
+}
+
+/// Whether resources are read from git's objects (at source_rev) rather than
+/// from the working tree
+pub(crate) fn reads_from_objects() -> bool {
+    *READS_FROM_OBJECTS
+}
+
+fn is_bare() -> bool {
+    git_output(&["rev-parse", "--is-bare-repository"]).is_some_and(|output| output.trim() == "true")
+}
+
+/// List every file in the local repo (relative to its root), excluding git's
+/// own files.  When resources are read from git's objects, these are the files
+/// at the commit they are read at
+pub(crate) fn list_local_files() -> Result<Vec<PathBuf>, String> {
+    fn walk(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.file_name().is_some_and(|name| name == ".git") {
+                continue;
+            }
+            if path.is_dir() {
+                walk(root, &path, files);
+            } else {
+                files.push(path.strip_prefix(root).unwrap().to_path_buf());
+            }
+        }
+    }
+
+    if reads_from_objects() {
+        let commit = resource::snapshot(&ResourceLocation::Local)?;
+        return Ok(git_output(&["ls-tree", "-r", "--name-only", &commit])
+            .unwrap_or_default()
+            .lines()
+            .map(PathBuf::from)
+            .collect());
     }
 
-    resource_dir
+    let root = local_resource_path()?;
+    let mut files = Vec::new();
+    walk(&root, &root, &mut files);
+    Ok(files)
 }
 
-/// Get latest commit hash (SHA1 ID) from local repo
-pub fn latest_local_commit_hash() -> String {
+/// Get latest commit hash (SHA1 ID) from local repo (at the ref resources are
+/// read at)
+pub(crate) fn latest_local_commit_hash() -> Result<String, String> {
     // See jakewilliami/gl :D
+    let rev = format!("{}^{{commit}}", source_rev());
     let mut cmd = Command::new("git");
     cmd.arg("-C");
-    cmd.arg(local_resource_path());
+    cmd.arg(local_resource_path()?);
     cmd.arg("rev-parse");
     cmd.arg(format!("--short={}", config::SHORT_HASH_LENGTH));
     cmd.arg("--verify");
-    cmd.arg("HEAD");
+    cmd.arg(&rev);
 
     let output = cmd
         .stdout(Stdio::piped())
         .output()
-        .expect("Failed to execute `git rev-parse` to obtain local commit hash");
+        .map_err(|e| format!("Failed to execute `git rev-parse`: {}", e))?;
 
     if !output.status.success() {
-        eprintln!(
-            "[ERROR] Could not run `git rev-parse --short={} --verify HEAD`",
-            config::SHORT_HASH_LENGTH
+        return Err(format!(
+            "Could not run `git rev-parse --short={} --verify {}`",
+            config::SHORT_HASH_LENGTH,
+            rev
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Resources in the working tree of the local repo with uncommitted changes
+/// (including new resources that git does not track yet)
+pub(crate) fn uncommitted_changes() -> Vec<String> {
+    let status = git_output(&["status", "--porcelain", "-z", "--untracked-files=all"]);
+    changed_resources(&status.unwrap_or_default())
+}
+
+// Whether a path in the repo is in the resource tree: under the class or
+// template directories, or a TeX file (which a class could \input)
+fn in_resource_tree(path: &str) -> bool {
+    path.starts_with(config::RESOURCE_PARENT)
+        || path.starts_with(config::TMPL_PARENT)
+        || is_tex_file(path)
+}
+
+fn is_tex_file(path: &str) -> bool {
+    let ext = path
+        .rsplit_once('.')
+        .map(|(_, ext)| ext)
+        .unwrap_or_default();
+    matches!(ext, "cls" | "sty" | "tex" | "bib")
+}
+
+// Changed resources from the output of `git status --porcelain -z`: one entry
+// per path, "XY path", ended by a NUL (with no quoting), where XY is the status
+// of the path in the index and in the working tree ("??" if untracked).
+// Renamed and copied paths are followed by another entry with the path they
+// were renamed or copied from.  Untracked files that are not TeX files (e.g.,
+// editor backups) do not count
+fn changed_resources(status: &str) -> Vec<String> {
+    let mut entries = status.split('\0');
+    let mut changed = Vec::new();
+    while let Some(entry) = entries.next() {
+        let (Some(xy), Some(path)) = (entry.get(..2), entry.get(3..)) else {
+            continue;
+        };
+        if xy.contains(['R', 'C']) {
+            entries.next();
+        }
+
+        let untracked = xy == "??";
+        if in_resource_tree(path) && (!untracked || is_tex_file(path)) {
+            changed.push(path.to_string());
+        }
+    }
+    changed
+}
+
+/// How the local repo's HEAD compares with the branch it tracks
+pub struct Upstream {
+    /// The remote-tracking branch, e.g. origin/master
+    pub name: String,
+    /// Commits in HEAD but not upstream
+    pub ahead: usize,
+    /// Commits upstream but not in HEAD
+    pub behind: usize,
+}
+
+/// Compare HEAD with its upstream branch (or with origin's main branch, if it
+/// has none).  This is only as recent as the last `git fetch`
+pub fn upstream() -> Option<Upstream> {
+    let name = git_output(&[
+        "rev-parse",
+        "--abbrev-ref",
+        "--symbolic-full-name",
+        "@{upstream}",
+    ])
+    .map(|name| name.trim().to_string())
+    .or_else(|| {
+        let name = format!("origin/{}", config::MAIN_BRANCH);
+        git_output(&["rev-parse", "--verify", "--quiet", &name]).map(|_| name)
+    })?;
+
+    let counts = git_output(&[
+        "rev-list",
+        "--left-right",
+        "--count",
+        &format!("HEAD...{}", name),
+    ])?;
+    let (ahead, behind) = counts.trim().split_once('\t')?;
+    Some(Upstream {
+        name,
+        ahead: ahead.parse().ok()?,
+        behind: behind.parse().ok()?,
+    })
+}
+
+/// Bring the local repo up to date with its upstream branch, if it can be done
+/// without merging
+pub(crate) fn pull() -> bool {
+    Command::new("git")
+        .arg("-C")
+        .arg(local_resource_dir())
+        .args(["pull", "--ff-only"])
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Check whether the local checkout is behind its upstream branch, and if so,
+/// offer to pull it, or to use the remote repository instead.  Returns false if
+/// the remote repository should be used
+pub fn check_upstream(dry_run: bool) -> bool {
+    // A ref given in the settings is read as it is
+    if !local_resource_dir().exists() || reads_from_objects() {
+        return true;
+    }
+    let Some(upstream) = upstream() else {
+        return true;
+    };
+    if upstream.behind == 0 {
+        return true;
+    }
+
+    let age = git_output(&["log", "-1", "--format=%cr", "HEAD"]).unwrap_or_default();
+    report::warn(format!(
+        "The local repository is {} commit(s) behind {} (as of its last fetch); its latest commit is from {}",
+        upstream.behind,
+        upstream.name,
+        age.trim()
+    ));
+
+    // Don't change anything on a dry run
+    if dry_run {
+        return true;
+    }
+
+    report::action(
+        Level::Debug,
+        Action::Prompt,
+        local_resource_dir().display(),
+        "Asking whether to update the local repository",
+    );
+    let choice = prompt::select(
+        "What would you like to do?",
+        &[
+            "Pull the latest changes (git pull --ff-only)",
+            "Use the remote repository instead",
+            "Continue with the local repository as it is",
+        ],
+        2,
+    );
+    match choice {
+        0 => {
+            if !pull() {
+                report::warn("Could not pull; continuing with the local repository as it is");
+            }
+            true
+        }
+        1 => false,
+        _ => true,
+    }
+}
+
+// Run a git command in the local repo, returning its output if it succeeds
+fn git_output(args: &[&str]) -> Option<String> {
+    git_bytes(args).map(|stdout| String::from_utf8_lossy(&stdout).into_owned())
+}
+
+fn git_bytes(args: &[&str]) -> Option<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(local_resource_path().ok()?)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    output.status.success().then_some(output.stdout)
+}
+
+/// Get the contents of a resource at a given commit in the local repo
+pub(crate) fn local_resource_at(commit: &str, resource: &str) -> Option<String> {
+    let resource = resource.trim_start_matches('/');
+    git_output(&["show", &format!("{}:{}", commit, resource)])
+}
+
+/// Like local_resource_at, but the raw contents, which may not be text
+pub(crate) fn local_resource_bytes_at(commit: &str, resource: &str) -> Option<Vec<u8>> {
+    let resource = resource.trim_start_matches('/');
+    git_bytes(&["show", &format!("{}:{}", commit, resource)])
+}
+
+/// Commits (abbreviated hash and summary) and changed files in the local repo
+/// between two commits
+pub(crate) fn local_changes_since(commit: &str, head: &str) -> Result<remote::Changes, String> {
+    let range = format!("{}..{}", commit, head);
+    let log = git_output(&[
+        "log",
+        &format!("--abbrev={}", config::SHORT_HASH_LENGTH),
+        "--format=%h %s",
+        &range,
+    ])
+    .ok_or_else(|| format!("Could not run `git log {}`", range))?;
+    let diff = git_output(&["diff", "--name-only", commit, head])
+        .ok_or_else(|| format!("Could not run `git diff {} {}`", commit, head))?;
+
+    let commits = log
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(sha, summary)| (sha.to_string(), summary.to_string()))
+        .collect();
+    let files = diff.lines().map(str::to_string).collect();
+    Ok((commits, files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_url_https() {
+        assert_eq!(
+            parse_remote_url("https://github.com/jakewilliami/tex-macros.git"),
+            Some(("jakewilliami".to_string(), "tex-macros".to_string()))
         );
-        process::exit(1);
     }
 
-    String::from_utf8_lossy(&output.stdout).into_owned()
+    #[test]
+    fn remote_url_scp_like() {
+        assert_eq!(
+            parse_remote_url("git@github.com:alice/tex-macros.git"),
+            Some(("alice".to_string(), "tex-macros".to_string()))
+        );
+    }
+
+    #[test]
+    fn remote_url_path_on_disk() {
+        assert_eq!(parse_remote_url("/home/alice/projects/tex-macros"), None);
+    }
+
+    #[test]
+    fn changed_resources_modified_and_added() {
+        let status = " M class/arteacle.cls\0A  macros/common.tex\0 M README.md\0";
+        assert_eq!(
+            changed_resources(status),
+            ["class/arteacle.cls", "macros/common.tex"]
+        );
+    }
+
+    #[test]
+    fn changed_resources_renamed() {
+        let status = "R  class/new name.cls\0class/old.cls\0";
+        assert_eq!(changed_resources(status), ["class/new name.cls"]);
+    }
+
+    #[test]
+    fn changed_resources_untracked() {
+        let status = "?? class/draft.sty\0?? class/.arteacle.cls.swp\0?? notes.txt\0";
+        assert_eq!(changed_resources(status), ["class/draft.sty"]);
+    }
+
+    #[test]
+    fn changed_resources_clean() {
+        assert!(changed_resources("").is_empty());
+    }
 }
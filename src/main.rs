@@ -1,22 +1,30 @@
 use clap::{ArgAction, Parser, Subcommand, crate_authors, crate_version};
-use std::{path::Path, process};
-
-mod config;
-mod file;
-mod freeze;
-mod input;
-mod local;
-mod remote;
-mod resource;
-mod sync;
-mod texmf;
-
-use config::*;
-use file::{LocalResource, LocalTemplate};
-use resource::{ResourceLocation, fetch_resource};
+use dialoguer::{Confirm, Select};
+use std::{
+    io::{self, IsTerminal},
+    path::Path,
+    process,
+};
+
+use mktex::{
+    bib, build, cache, catalogue,
+    config::*,
+    doctor,
+    figure::{self, FigureStyle},
+    file::{self, InstallTarget},
+    freeze, info,
+    kind::{self, DocumentKind},
+    local,
+    plan::{self, PlanFormat},
+    prompt::{self, Prompter},
+    report::{self, Level, LogFormat},
+    resource::{ResourceLocation, fetch_resource, try_fetch_resource},
+    scaffold,
+    settings::{Engine, InstallTo, SETTINGS, Settings},
+    status, texmf, upgrade, watch,
+};
 
 // TODO:
-//   - better logging
 //   - add version history to readme
 //   - fix broken pipe: https://stackoverflow.com/a/65760807/12069968
 //   - fix -c freeze crash
@@ -25,15 +33,11 @@ use resource::{ResourceLocation, fetch_resource};
 //   - allow freeze options (e.g., don't assume the user wants to use freeze with -c)
 //   - more idiomatic result handling
 //   - allow freeze to accept commit id
-//   - class option local with no texmf
 //   - freeze more than just class
 //   - no-option default?
 //   - decouple from tex-macros repo as much as possible
 //   - author
-//   - general class option?
-//   - bibligraphy file option
 //   - formal letter option
-//   - figure option
 //   - poi option
 
 #[derive(Parser)]
@@ -92,6 +96,23 @@ struct Cli {
     )]
     class: Option<bool>,
 
+    /// Use any class file from the macros repository (e.g., class/foo.cls)
+    #[arg(
+        long = "class-file",
+        action = ArgAction::Set,
+        value_name = "resource",
+    )]
+    class_file: Option<String>,
+
+    /// Template to use with --class-file [default: a minimal document]
+    #[arg(
+        long = "template",
+        action = ArgAction::Set,
+        value_name = "resource",
+        requires("class_file"),
+    )]
+    template: Option<String>,
+
     /// Use letter class
     #[arg(
         short = 'L',
@@ -129,6 +150,104 @@ struct Cli {
     )]
     dry_run: Option<bool>,
 
+    /// Format of the plan printed by --dry-run
+    #[arg(
+        long = "plan-format",
+        action = ArgAction::Set,
+        value_name = "format",
+        default_value = "text",
+    )]
+    plan_format: PlanFormat,
+
+    /// Show more detail, such as resources fetched and prompts
+    #[arg(
+        short = 'v',
+        long = "verbose",
+        global = true,
+        action = ArgAction::SetTrue,
+        conflicts_with = "quiet",
+    )]
+    verbose: bool,
+
+    /// Show only warnings and errors (repeat to show only errors)
+    #[arg(
+        short = 'q',
+        long = "quiet",
+        global = true,
+        action = ArgAction::Count,
+    )]
+    quiet: u8,
+
+    /// Format of log messages, which are written to standard error
+    #[arg(
+        long = "log-format",
+        global = true,
+        action = ArgAction::Set,
+        value_name = "format",
+        default_value = "text",
+    )]
+    log_format: LogFormat,
+
+    /// Where to install class files and their dependencies [default: texmf, or
+    /// as set in the settings file]
+    #[arg(
+        long = "install-to",
+        action = ArgAction::Set,
+        value_name = "target",
+    )]
+    install_to: Option<InstallTo>,
+
+    /// Write a bibliography file alongside the document
+    #[arg(
+        long = "bib",
+        action = ArgAction::SetTrue,
+        num_args = 0,
+    )]
+    bib: Option<bool>,
+
+    /// Add an entry to the bibliography, given as BibTeX or a DOI (implies
+    /// --bib; may be repeated)
+    #[arg(
+        long = "bib-entry",
+        action = ArgAction::Append,
+        value_name = "BibTeX or DOI",
+    )]
+    bib_entry: Vec<String>,
+
+    /// Read bibliography entries (BibTeX, or one DOI per line) from standard
+    /// input (implies --bib)
+    #[arg(
+        long = "bib-stdin",
+        action = ArgAction::SetTrue,
+        num_args = 0,
+    )]
+    bib_stdin: Option<bool>,
+
+    /// Compile the document after creating it
+    #[arg(
+        long = "build",
+        action = ArgAction::SetTrue,
+        num_args = 0,
+    )]
+    build: Option<bool>,
+
+    /// Engine used to compile the document [default: detected, or as set in
+    /// the settings file]
+    #[arg(
+        long = "engine",
+        action = ArgAction::Set,
+        value_name = "engine",
+    )]
+    engine: Option<Engine>,
+
+    /// Do not refresh the texmf filename database (ls-R) after installing
+    #[arg(
+        long = "no-mktexlsr",
+        action = ArgAction::SetTrue,
+        num_args = 0,
+    )]
+    no_mktexlsr: Option<bool>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 
@@ -144,17 +263,243 @@ enum Commands {
     Freeze,
     /// Print local texmf directory
     Texmf,
+    /// Check that everything mktex needs is available
+    Doctor,
+    /// Show installed resources, their versions, and whether they are in sync
+    Status,
+    /// List classes, packages, and templates in the macros repository
+    List,
+    /// Search classes, packages, and templates in the macros repository
+    Search {
+        /// Term to search for in resource paths and descriptions
+        #[arg(value_name = "term")]
+        term: String,
+    },
+    /// Show the class a document uses, the version it was made with, and
+    /// what has changed upstream since
+    Info {
+        /// Document to inspect
+        #[arg(value_name = "file name", default_value = "document.tex")]
+        file: String,
+
+        /// Print the information as JSON
+        #[arg(long = "json", action = ArgAction::SetTrue)]
+        json: bool,
+    },
+    /// Apply changes made to a document's template since the document was
+    /// made, and install the latest version of its class
+    Upgrade {
+        /// Document to upgrade
+        #[arg(value_name = "file name", default_value = "document.tex")]
+        file: String,
+
+        /// Template the document was made from [default: the template for
+        /// the document's class]
+        #[arg(long = "template", value_name = "resource")]
+        template: Option<String>,
+    },
+    /// Compile a document
+    Build {
+        /// Document to compile
+        #[arg(value_name = "file name", default_value = "document.tex")]
+        file: String,
+
+        /// Engine used to compile the document [default: detected, or as set
+        /// in the settings file]
+        #[arg(long = "engine", value_name = "engine")]
+        engine: Option<Engine>,
+    },
+    /// Make a new project directory with a document and supporting files
+    New {
+        /// Kind of document
+        #[arg(value_name = "kind")]
+        kind: DocumentKind,
+
+        /// Project directory
+        #[arg(value_name = "directory")]
+        dir: String,
+
+        /// File name of the main document
+        #[arg(
+            long = "file",
+            value_name = "file name",
+            default_value = "document.tex"
+        )]
+        file: String,
+
+        /// Initialise a git repository with an initial commit
+        #[arg(long = "git", action = ArgAction::SetTrue)]
+        git: bool,
+    },
+    /// Make a standalone figure and add it to a document
+    Figure {
+        /// Name of the figure
+        #[arg(value_name = "name")]
+        name: String,
+
+        /// Document to add the figure to
+        #[arg(long = "doc", value_name = "file name", default_value = "document.tex")]
+        doc: String,
+
+        /// Kind of figure
+        #[arg(long = "style", value_name = "style", default_value = "tikz")]
+        style: FigureStyle,
+
+        /// Template for the figure in the macros repository [default:
+        /// templates/figure/<style>.tex, if it exists]
+        #[arg(long = "template", value_name = "resource")]
+        template: Option<String>,
+
+        /// Include the compiled figure with \includegraphics rather than
+        /// \includestandalone
+        #[arg(long = "graphics", action = ArgAction::SetTrue)]
+        graphics: bool,
+    },
+    /// Take the steps of a plan saved from --dry-run --plan-format json
+    Apply {
+        /// Plan to apply
+        #[arg(value_name = "plan file")]
+        plan: String,
+    },
+    /// Manage responses cached from the resource repository
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Rebuild a document whenever it or the local class files change
+    Watch {
+        /// Document to watch
+        #[arg(value_name = "file name", default_value = "document.tex")]
+        file: String,
+
+        /// Engine used to compile the document [default: detected, or as set
+        /// in the settings file]
+        #[arg(long = "engine", value_name = "engine")]
+        engine: Option<Engine>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Print the directory responses are cached in
+    Dir,
+    /// Remove every cached response
+    Clear,
+}
+
+// Ask questions on the terminal, if there is one to answer them
+struct Terminal;
+
+impl Prompter for Terminal {
+    fn confirm(&self, prompt: &str, default: bool) -> bool {
+        if !io::stdin().is_terminal() {
+            return default;
+        }
+        Confirm::new()
+            .with_prompt(prompt)
+            .default(default)
+            .interact()
+            .unwrap_or(default)
+    }
+
+    fn select(&self, prompt: &str, options: &[&str], default: usize) -> usize {
+        if !io::stdin().is_terminal() {
+            return default;
+        }
+        Select::new()
+            .with_prompt(prompt)
+            .items(options)
+            .default(default)
+            .interact()
+            .unwrap_or(default)
+    }
+}
+
+// Whether a command fetches resources (the main command, without a
+// subcommand, does)
+fn fetches_resources(command: &Option<Commands>) -> bool {
+    !matches!(
+        command,
+        Some(
+            Commands::Texmf
+                | Commands::Doctor
+                | Commands::Info { .. }
+                | Commands::Build { .. }
+                | Commands::Apply { .. }
+                | Commands::Cache { .. }
+        )
+    )
+}
+
+// The value of a library call, or else report its error and exit
+fn or_exit<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|e| {
+        report::error(e);
+        process::exit(1);
+    })
+}
+
+// Write a bibliography alongside the document, if one was asked for
+fn add_bibliography(
+    cli: &Cli,
+    out_dir: &String,
+    out_file: &String,
+    resource_location: &ResourceLocation,
+    dry_run: bool,
+) -> bool {
+    let bib_stdin = cli.bib_stdin.unwrap_or_default();
+    if !cli.bib.unwrap_or_default() && cli.bib_entry.is_empty() && !bib_stdin {
+        return true;
+    }
+
+    let mut snippets = cli.bib_entry.clone();
+    if bib_stdin {
+        let input = or_exit(
+            io::read_to_string(io::stdin())
+                .map_err(|e| format!("Cannot read from standard input: {}", e)),
+        );
+        snippets.extend(bib::parse_snippets(&input));
+    }
+
+    if let Err(e) = bib::write_bibliography(
+        &Path::new(out_dir).join(BIB_FILE),
+        &snippets,
+        resource_location,
+        dry_run,
+    ) {
+        report::error(e);
+        return false;
+    }
+    if let Err(e) = bib::use_bibliography(&Path::new(out_dir).join(out_file), dry_run) {
+        report::error(e);
+        return false;
+    }
+    true
 }
 
 fn main() {
     let mut cli = Cli::parse();
 
-    let resource_location = if let Some(local) = cli.local {
-        if local {
-            ResourceLocation::Local
-        } else {
-            ResourceLocation::Remote
-        }
+    let log_level = match (cli.verbose, cli.quiet) {
+        (true, _) => Level::Debug,
+        (false, 0) => Level::Info,
+        (false, 1) => Level::Warn,
+        (false, _) => Level::Error,
+    };
+    report::set_reporter(report::logger(log_level, cli.log_format));
+    prompt::set_prompter(Terminal);
+    // Settings that cannot be read are an error here, rather than falling
+    // back to the defaults
+    or_exit(Settings::load());
+
+    // Offer to update a local checkout that has fallen behind before fetching
+    // anything from it
+    let local = cli.local.unwrap_or_default();
+    let resource_location = if local
+        && (!fetches_resources(&cli.command)
+            || local::check_upstream(cli.dry_run.unwrap_or_default()))
+    {
+        ResourceLocation::Local
     } else {
         ResourceLocation::Remote
     };
@@ -162,10 +507,10 @@ fn main() {
     // Parse subcommands and exit
     match cli.command {
         Some(Commands::Freeze) => {
-            let cls_contents = fetch_resource(CLS_RESOURCE, &resource_location);
+            let cls_contents = or_exit(fetch_resource(CLS_RESOURCE, &resource_location));
             println!(
                 "{}",
-                freeze::expand_input_paths(cls_contents, &resource_location)
+                or_exit(freeze::expand_input_paths(cls_contents, &resource_location))
             );
             process::exit(0);
         }
@@ -173,131 +518,296 @@ fn main() {
             if let Some(texmf_path) = texmf::texmf() {
                 println!("{}", texmf_path.display());
             } else {
-                eprintln!("[ERROR] Could not find local texmf directory");
+                report::error("Could not find local texmf directory");
+                process::exit(1);
+            }
+            process::exit(0);
+        }
+        Some(Commands::Doctor) => {
+            or_exit(doctor::run(&resource_location));
+            process::exit(0);
+        }
+        Some(Commands::Status) => {
+            let install_target = InstallTarget::new(
+                cli.install_to.unwrap_or(SETTINGS.install.to),
+                Path::new("."),
+                &resource_location,
+            );
+            or_exit(status::print_status(&resource_location, &install_target));
+            process::exit(0);
+        }
+        Some(Commands::List) => {
+            or_exit(catalogue::print_catalogue(&resource_location, None));
+            process::exit(0);
+        }
+        Some(Commands::Search { ref term }) => {
+            or_exit(catalogue::print_catalogue(&resource_location, Some(term)));
+            process::exit(0);
+        }
+        Some(Commands::Info { ref file, json }) => {
+            let install_target = InstallTarget::new(
+                cli.install_to.unwrap_or(SETTINGS.install.to),
+                Path::new("."),
+                &resource_location,
+            );
+            or_exit(info::print_info(
+                Path::new(file),
+                &resource_location,
+                &install_target,
+                json,
+            ));
+            process::exit(0);
+        }
+        Some(Commands::Upgrade {
+            ref file,
+            ref template,
+        }) => {
+            let install_target = InstallTarget::new(
+                cli.install_to.unwrap_or(SETTINGS.install.to),
+                Path::new("."),
+                &resource_location,
+            );
+            let clean = or_exit(upgrade::upgrade(
+                Path::new(file),
+                template.as_deref(),
+                &resource_location,
+                &install_target,
+                cli.dry_run.unwrap_or_default(),
+            ));
+            if cli.dry_run.unwrap_or_default() {
+                plan::print_plan(&plan::take(), cli.plan_format);
+            }
+            process::exit(if clean { 0 } else { 1 });
+        }
+        Some(Commands::Build { ref file, engine }) => {
+            let ok = or_exit(build::build(Path::new(file), engine.or(cli.engine)));
+            process::exit(if ok { 0 } else { 1 });
+        }
+        Some(Commands::Watch { ref file, engine }) => {
+            or_exit(watch::watch(
+                Path::new(file),
+                engine.or(cli.engine),
+                cli.install_to.unwrap_or(SETTINGS.install.to),
+            ));
+        }
+        Some(Commands::Figure {
+            ref name,
+            ref doc,
+            style,
+            ref template,
+            graphics,
+        }) => {
+            or_exit(figure::make_figure(
+                name,
+                Path::new(doc),
+                style,
+                template.as_deref(),
+                graphics,
+                &resource_location,
+                cli.dry_run.unwrap_or_default(),
+            ));
+            if cli.dry_run.unwrap_or_default() {
+                plan::print_plan(&plan::take(), cli.plan_format);
+            }
+            process::exit(0);
+        }
+        Some(Commands::New {
+            kind,
+            ref dir,
+            ref file,
+            git,
+        }) => {
+            let dry_run = cli.dry_run.unwrap_or_default();
+            let install_target = InstallTarget::new(
+                cli.install_to.unwrap_or(SETTINGS.install.to),
+                Path::new(dir),
+                &resource_location,
+            );
+            let installed = or_exit(scaffold::scaffold(
+                kind,
+                dir,
+                file,
+                &resource_location,
+                &install_target,
+                dry_run,
+            ));
+            or_exit(file::finish_install(
+                &installed,
+                &install_target,
+                dir,
+                cli.no_mktexlsr.unwrap_or_default(),
+                dry_run,
+            ));
+            if !add_bibliography(&cli, dir, file, &resource_location, dry_run) {
+                process::exit(1);
+            }
+            if git {
+                scaffold::git_init(Path::new(dir), dry_run);
+            }
+            if dry_run {
+                plan::print_plan(&plan::take(), cli.plan_format);
+            }
+            if cli.build.unwrap_or_default()
+                && !dry_run
+                && !or_exit(build::build(&Path::new(dir).join(file), cli.engine))
+            {
                 process::exit(1);
             }
             process::exit(0);
         }
+        Some(Commands::Cache { ref command }) => {
+            let ok = match command {
+                CacheCommand::Dir => match cache::cache_dir() {
+                    Some(dir) => {
+                        println!("{}", dir.display());
+                        true
+                    }
+                    None => {
+                        report::error("Cannot find the cache directory");
+                        false
+                    }
+                },
+                CacheCommand::Clear => match cache::clear() {
+                    Ok(removed) => {
+                        report::info(format!("Removed {} cached response(s)", removed));
+                        true
+                    }
+                    Err(e) => {
+                        report::error(e);
+                        false
+                    }
+                },
+            };
+            process::exit(if ok { 0 } else { 1 });
+        }
+        Some(Commands::Apply { ref plan }) => {
+            let steps = plan::read_plan(Path::new(plan)).unwrap_or_else(|e| {
+                report::error(format!("Could not read plan {:?}: {}", plan, e));
+                process::exit(1);
+            });
+            let ok = plan::apply(&steps);
+            process::exit(if ok { 0 } else { 1 });
+        }
         None => {}
     }
 
     let mut opt_used = false;
-    let out_dir = cli.dir.unwrap().to_string();
-    let out_file = cli.file.unwrap().to_string();
+    let out_dir = cli.dir.clone().unwrap();
+    let out_file = cli.file.clone().unwrap();
     let dry_run = cli.dry_run.unwrap_or_default();
+    let mut installed = Vec::new();
+    let install_target = InstallTarget::new(
+        cli.install_to.unwrap_or(SETTINGS.install.to),
+        Path::new(&out_dir),
+        &resource_location,
+    );
 
-    // Make article class file
-    if let Some(use_class) = cli.class {
-        if use_class {
-            opt_used = true;
-            eprintln!("[WARN] --class option is deprecated since v1.8.1.  Use --article instead.");
-            cli.article = Some(true);
-        }
+    // Article class was previously made with --class
+    if cli.class == Some(true) {
+        opt_used = true;
+        report::warn("--class option is deprecated since v1.8.1.  Use --article instead.");
+        cli.article = Some(true);
     }
-    if let Some(use_article) = cli.article {
-        if use_article {
-            opt_used = true;
-            let cls = LocalResource {
-                resource_path: CLS_RESOURCE.to_string(),
-                resource_location: &resource_location,
-                template: Some(LocalTemplate {
-                    template_path: TMPL_RESOURCE.to_string(),
-                    out_dir: &out_dir,
-                    out_file: &out_file,
-                }),
-            };
-            file::write_resource(cls.clone(), dry_run);
-
-            // Write sourced files required by the class
-            println!("[INFO] Checking sync status of local source files...");
-            for source_file in input::sourced_files(cls) {
-                file::write_resource(source_file, dry_run)
-            }
-            println!("[INFO] Done")
+    let mut kinds = Vec::new();
+    if cli.article == Some(true) {
+        kinds.push(DocumentKind::Article);
+    }
+    if cli.letter == Some(true) {
+        if cli.formal.unwrap_or_default() {
+            kinds.push(DocumentKind::FormalLetter);
+        } else {
+            kinds.push(DocumentKind::Letter);
         }
-    };
+    }
+    if cli.beamer == Some(true) {
+        kinds.push(DocumentKind::Beamer);
+    }
 
-    // Make letter file
-    if let Some(use_letter) = cli.letter {
-        if use_letter {
-            opt_used = true;
-            let template = if let Some(formal_letter) = cli.formal {
-                if formal_letter {
-                    LTR_FML_TMPL_RESOURCE
-                } else {
-                    LTR_TMPL_RESOURCE
-                }
-            } else {
-                LTR_TMPL_RESOURCE
-            };
-            let cls = LocalResource {
-                resource_path: LTR_RESOURCE.to_string(),
-                resource_location: &resource_location,
-                template: Some(LocalTemplate {
-                    template_path: template.to_string(),
-                    out_dir: &out_dir,
-                    out_file: &out_file,
-                }),
-            };
-            file::write_resource(cls.clone(), dry_run);
+    for kind in kinds {
+        opt_used = true;
+        installed.extend(or_exit(kind::write_document(
+            kind,
+            &resource_location,
+            &install_target,
+            &out_dir,
+            &out_file,
+            dry_run,
+        )));
+    }
 
-            // Write sourced files required by the class
-            println!("[INFO] Checking sync status of local source files...");
-            for source_file in input::sourced_files(cls) {
-                file::write_resource(source_file, dry_run)
-            }
-            println!("[INFO] Done")
+    // Make document with any other class
+    if let Some(class_file) = &cli.class_file {
+        opt_used = true;
+        if !class_file.ends_with(".cls") {
+            report::error(format!(
+                "--class-file must be a .cls file: {:?}",
+                class_file
+            ));
+            process::exit(1);
         }
-    };
-
-    // Make beamer file
-    if let Some(use_beamer) = cli.beamer {
-        if use_beamer {
-            opt_used = true;
-            // Custom Beamer theme files
-            for file in [
-                BMR_THEME_COLOUR,
-                BMR_THEME_INNER,
-                BMR_THEME_OUTER,
-                BMR_THEME_MAIN,
-            ] {
-                let theme_file = Path::new(BMR_THEME_PATH).join(file);
-                let sty = LocalResource {
-                    resource_path: theme_file.display().to_string(),
-                    resource_location: &resource_location,
-                    template: None,
-                };
-                file::write_resource(sty, dry_run);
+        for resource in [Some(class_file), cli.template.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            if or_exit(try_fetch_resource(resource, &resource_location)).is_none() {
+                report::error(format!("No such resource {:?}", resource));
+                process::exit(1);
             }
+        }
+        installed.extend(or_exit(kind::write_class_document(
+            class_file,
+            cli.template.as_deref(),
+            Vec::new(),
+            &resource_location,
+            &install_target,
+            &out_dir,
+            &out_file,
+            dry_run,
+        )));
+    }
 
-            // Main Beamer class file
-            let cls = LocalResource {
-                resource_path: BMR_RESOURCE.to_string(),
-                resource_location: &resource_location,
-                template: Some(LocalTemplate {
-                    template_path: BMR_TMPL_RESOURCE.to_string(),
-                    out_dir: &out_dir,
-                    out_file: &out_file,
-                }),
-            };
-            file::write_resource(cls, dry_run);
+    if opt_used {
+        or_exit(file::finish_install(
+            &installed,
+            &install_target,
+            &out_dir,
+            cli.no_mktexlsr.unwrap_or_default(),
+            dry_run,
+        ));
+        if !add_bibliography(&cli, &out_dir, &out_file, &resource_location, dry_run) {
+            process::exit(1);
+        }
+        if dry_run {
+            plan::print_plan(&plan::take(), cli.plan_format);
         }
     }
 
+    // Compile the newly created document
+    if cli.build.unwrap_or_default()
+        && opt_used
+        && !dry_run
+        && !or_exit(build::build(
+            &Path::new(&out_dir).join(&out_file),
+            cli.engine,
+        ))
+    {
+        process::exit(1);
+    }
+
     // Check if dry run is given without other options
     if dry_run && !opt_used {
-        eprintln!(
-            "[ERROR] --dry-run argument passed without another option.  Cannot dry run with prespecified no intent.  Use -h for help."
+        report::error(
+            "--dry-run argument passed without another option.  Cannot dry run with prespecified no intent.  Use -h for help.",
         );
         process::exit(1);
     }
 
     // Check that file is parsed with some other options
     if !opt_used {
-        eprintln!(
-            "[ERROR] Must used on of the command line options if a file is specified.  Use --h for help.  File specified: {:?}",
+        report::error(format!(
+            "Must used on of the command line options if a file is specified.  Use --h for help.  File specified: {:?}",
             &out_file
-        );
+        ));
         process::exit(1);
     }
 
@@ -0,0 +1,502 @@
+// Plans of what an install would do
+//
+// On a dry run, nothing is written: instead, each step that would have been
+// taken is recorded, in order, so that the whole plan can be shown to the user
+// (or saved as JSON) and applied later with `mktex apply`.  Planning never
+// writes to the file system.  Steps name the resources they need rather than
+// holding their contents, so resources and templates are only fetched (and
+// class versions, merges, and DOI lookups only worked out) when the plan is
+// applied.  Planning does still read what decides which steps there are: the
+// commit the plan is made at (for the remote repository, one request), the
+// class (for the files it \inputs), and a project's manifest.
+
+use super::{
+    bib,
+    figure::{self, FigureStyle},
+    file,
+    report::{self, Action, Level},
+    resource::{self, ResourceLocation, try_fetch_resource},
+    scaffold, sync, texmf, upgrade,
+};
+use clap::ValueEnum;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "step", rename_all = "kebab-case")]
+pub enum Step {
+    /// Create a directory (and its parents)
+    CreateDir { path: PathBuf },
+    /// Move a resource installed with the old texmf layout into the new one
+    MoveResource { from: PathBuf, to: PathBuf },
+    /// Install a resource that is not yet installed
+    WriteResource {
+        resource: String,
+        location: ResourceLocation,
+        /// Commit of the resource repository to fetch the resource at
+        commit: String,
+        path: PathBuf,
+    },
+    /// Replace an installed resource if it is out of sync
+    UpdateResource {
+        resource: String,
+        location: ResourceLocation,
+        /// Commit of the resource repository to fetch the resource at
+        commit: String,
+        path: PathBuf,
+        /// Whether to ask the user first, as an install would (rather than
+        /// refreshing it without asking, as an upgrade would)
+        ask: bool,
+    },
+    /// Write a document from a template (or a minimal document using the
+    /// class, if there is no template), stamped with the version of the class
+    WriteTemplate {
+        template: Option<String>,
+        class_resource: String,
+        location: ResourceLocation,
+        commit: String,
+        path: PathBuf,
+        /// Whether the document already existed when the plan was made (if
+        /// so, the user is asked before it is overwritten; if not, it is not
+        /// overwritten)
+        overwrite: bool,
+    },
+    /// Write a file, from a resource if one is given and it exists, or else
+    /// with the given contents
+    WriteFile {
+        path: PathBuf,
+        resource: Option<String>,
+        location: ResourceLocation,
+        commit: String,
+        contents: String,
+    },
+    /// Add a line to a latexmkrc (creating it if needed) so that latexmk can
+    /// find resources in the project
+    WriteLatexmkrc { path: PathBuf, line: String },
+    /// Write (or add entries to) a bibliography, from a template in the
+    /// resource repository if it does not exist yet
+    WriteBibliography {
+        path: PathBuf,
+        /// BibTeX entries, or DOIs to look up
+        entries: Vec<String>,
+        location: ResourceLocation,
+        commit: String,
+    },
+    /// Add the lines needed to use the bibliography to a document
+    UseBibliography { path: PathBuf },
+    /// Add a figure to a document
+    AddFigure {
+        doc: PathBuf,
+        name: String,
+        style: FigureStyle,
+        graphics: bool,
+    },
+    /// Merge the changes made to a document's template since it was made into
+    /// it, from the template as it is at the given commit
+    UpgradeDocument {
+        path: PathBuf,
+        /// Template the document was made from, if not the one for its class
+        template: Option<String>,
+        location: ResourceLocation,
+        commit: String,
+    },
+    /// Initialise a git repository with everything in the directory committed
+    GitInit { path: PathBuf },
+    /// Refresh the filename database of a texmf tree
+    RefreshDatabase { tree: PathBuf },
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Step::CreateDir { path } => write!(f, "create directory {:?}", path),
+            Step::MoveResource { from, to } => write!(f, "move resource {:?} to {:?}", from, to),
+            Step::WriteResource { resource, path, .. } => {
+                write!(f, "write resource {:?} to {:?}", resource, path)
+            }
+            Step::UpdateResource {
+                resource,
+                path,
+                ask,
+                ..
+            } => {
+                write!(
+                    f,
+                    "update resource {:?} at {:?} if out of sync",
+                    resource, path
+                )?;
+                if *ask {
+                    write!(f, " (asking first)")?;
+                }
+                Ok(())
+            }
+            Step::WriteTemplate {
+                template,
+                class_resource,
+                path,
+                overwrite,
+                ..
+            } => {
+                match template {
+                    Some(template) => write!(f, "write template {:?}", template)?,
+                    None => write!(f, "write skeleton for class {:?}", class_resource)?,
+                }
+                write!(
+                    f,
+                    " to {:?}, stamped with the version of {:?}",
+                    path, class_resource
+                )?;
+                if *overwrite {
+                    write!(f, " (asking before overwriting it)")?;
+                }
+                Ok(())
+            }
+            Step::WriteFile { path, resource, .. } => match resource {
+                Some(resource) => write!(f, "write {:?} from {:?}", path, resource),
+                None => write!(f, "write {:?}", path),
+            },
+            Step::WriteLatexmkrc { path, .. } => {
+                write!(f, "add project resources to {:?}", path)
+            }
+            Step::WriteBibliography { path, entries, .. } => {
+                write!(
+                    f,
+                    "add {} entries to bibliography {:?}",
+                    entries.len(),
+                    path
+                )
+            }
+            Step::UseBibliography { path } => write!(f, "add bibliography to {:?}", path),
+            Step::AddFigure { doc, name, .. } => write!(f, "add figure {:?} to {:?}", name, doc),
+            Step::UpgradeDocument { path, .. } => {
+                write!(f, "merge template changes into {:?}", path)
+            }
+            Step::GitInit { path } => write!(f, "initialise a git repository in {:?}", path),
+            Step::RefreshDatabase { tree } => {
+                write!(f, "refresh filename database in {:?}", tree)
+            }
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlanFormat {
+    /// One numbered step per line
+    #[default]
+    Text,
+    /// A JSON array of steps, which can be applied with `mktex apply`
+    Json,
+}
+
+lazy_static! {
+    static ref PLAN: Mutex<Vec<Step>> = Mutex::new(Vec::new());
+}
+
+/// Record a step that would have been taken
+pub(crate) fn record(step: Step) {
+    report::debug(format!("Planning to {}", step));
+    PLAN.lock().unwrap().push(step);
+}
+
+/// Take the steps recorded so far
+pub fn take() -> Vec<Step> {
+    std::mem::take(&mut *PLAN.lock().unwrap())
+}
+
+/// Print a plan (as the output of the command)
+pub fn print_plan(steps: &[Step], format: PlanFormat) {
+    match format {
+        PlanFormat::Text => {
+            if steps.is_empty() {
+                report::output("Nothing to do");
+            }
+            for (i, step) in steps.iter().enumerate() {
+                report::output(format!("{}. {}", i + 1, step));
+            }
+        }
+        PlanFormat::Json => report::output(serde_json::to_string_pretty(steps).unwrap()),
+    }
+}
+
+/// Read a plan saved as JSON
+pub fn read_plan(path: &Path) -> Result<Vec<Step>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&contents).map_err(|e| e.to_string())
+}
+
+fn write(path: &Path, contents: &str, message: String) -> Result<(), String> {
+    report::action(Level::Info, Action::Write, path.display(), message);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, contents).map_err(|e| e.to_string())
+}
+
+// Fetch a resource as it was when the plan was made
+fn fetch_at(resource: &str, location: &ResourceLocation, commit: &str) -> Result<String, String> {
+    resource::pin_snapshot(location, commit)?;
+    try_fetch_resource(resource, location)?
+        .ok_or_else(|| format!("no resource {:?} at commit {}", resource, commit))
+}
+
+// Take a step of a plan
+fn apply_step(step: &Step) -> Result<(), String> {
+    match step {
+        Step::CreateDir { path } => {
+            report::action(
+                Level::Info,
+                Action::Write,
+                path.display(),
+                format!("Creating directory {:?}", path),
+            );
+            fs::create_dir_all(path).map_err(|e| e.to_string())
+        }
+        Step::MoveResource { from, to } => {
+            report::action(
+                Level::Info,
+                Action::Write,
+                to.display(),
+                format!("Moving resource from {:?} to {:?}", from, to),
+            );
+            texmf::move_file(from, to)
+        }
+        Step::WriteResource {
+            resource,
+            location,
+            commit,
+            path,
+        } => {
+            let contents = fetch_at(resource, location, commit)?;
+            write(
+                path,
+                &contents,
+                format!("Writing resource {:?} to {:?}", resource, path),
+            )
+        }
+        Step::UpdateResource {
+            resource,
+            location,
+            commit,
+            path,
+            ask,
+        } => {
+            let contents = fetch_at(resource, location, commit)?;
+            if sync::check_resource(path, &contents) {
+                report::action(
+                    Level::Info,
+                    Action::Skip,
+                    path.display(),
+                    format!("Resource {:?} is in sync", path),
+                );
+                return Ok(());
+            }
+            if *ask && !file::confirm_update(path) {
+                return Ok(());
+            }
+            write(
+                path,
+                &contents,
+                format!("Updating resource {:?} at {:?}", resource, path),
+            )
+        }
+        Step::WriteTemplate {
+            template,
+            class_resource,
+            location,
+            commit,
+            path,
+            overwrite,
+        } => {
+            // Something may have been written there since the plan was made
+            if path.exists() && !overwrite {
+                return Err(format!(
+                    "{:?} exists now, but did not when the plan was made",
+                    path
+                ));
+            }
+            if !file::confirm_overwrite(path) {
+                return Ok(());
+            }
+            let cls_contents = fetch_at(class_resource, location, commit)?;
+            file::write_stamped_template(
+                template.as_deref(),
+                class_resource,
+                &cls_contents,
+                location,
+                path,
+            )
+        }
+        Step::WriteFile {
+            path,
+            resource,
+            location,
+            commit,
+            contents,
+        } => {
+            let fetched = match resource {
+                Some(resource) => {
+                    resource::pin_snapshot(location, commit)?;
+                    try_fetch_resource(resource, location)?
+                }
+                None => None,
+            };
+            let contents = fetched.as_deref().unwrap_or(contents);
+            write(path, contents, format!("Writing {:?}", path))
+        }
+        Step::WriteLatexmkrc { path, line } => file::add_to_latexmkrc(path, line),
+        Step::WriteBibliography {
+            path,
+            entries,
+            location,
+            commit,
+        } => {
+            resource::pin_snapshot(location, commit)?;
+            bib::write_bibliography(path, entries, location, false)
+        }
+        Step::UseBibliography { path } => bib::use_bibliography(path, false).map(|_| ()),
+        Step::AddFigure {
+            doc,
+            name,
+            style,
+            graphics,
+        } => figure::insert_figure(doc, name, *style, *graphics, false),
+        Step::UpgradeDocument {
+            path,
+            template,
+            location,
+            commit,
+        } => {
+            resource::pin_snapshot(location, commit)?;
+            match upgrade::upgrade_document(path, template.as_deref(), location)? {
+                true => Ok(()),
+                false => Err("conflicts are left to resolve by hand".to_string()),
+            }
+        }
+        Step::GitInit { path } => {
+            scaffold::git_init(path, false);
+            Ok(())
+        }
+        Step::RefreshDatabase { tree } => {
+            report::info(format!("Refreshing filename database in {:?}", tree));
+            match texmf::refresh_filename_database(tree) {
+                true => Ok(()),
+                false => Err(format!(
+                    "could not refresh filename database; run `mktexlsr {}` manually",
+                    tree.display()
+                )),
+            }
+        }
+    }
+}
+
+/// Take the steps of a plan, in order.  Steps that fail are reported, and do
+/// not stop the rest.  Returns false if any step failed
+pub fn apply(steps: &[Step]) -> bool {
+    let mut ok = true;
+    for step in steps {
+        if let Err(e) = apply_step(step) {
+            report::error(format!("Could not {}: {}", step, e));
+            ok = false;
+        }
+    }
+    ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_json_round_trip() {
+        let steps = vec![
+            Step::CreateDir {
+                path: PathBuf::from("/tmp/texmf/tex/latex/local"),
+            },
+            Step::WriteResource {
+                resource: "class/arteacle.cls".to_string(),
+                location: ResourceLocation::Remote,
+                commit: "1d72b1d".repeat(5) + "12345",
+                path: PathBuf::from("/tmp/texmf/tex/latex/local/arteacle.cls"),
+            },
+            Step::WriteTemplate {
+                template: Some("templates/arteacle.tex".to_string()),
+                class_resource: "class/arteacle.cls".to_string(),
+                location: ResourceLocation::Local,
+                commit: "1d72b1d-dirty".to_string(),
+                path: PathBuf::from("./document.tex"),
+                overwrite: false,
+            },
+            Step::WriteFile {
+                path: PathBuf::from("./.gitignore"),
+                resource: None,
+                location: ResourceLocation::Remote,
+                commit: "1d72b1d".to_string(),
+                contents: "*.aux\n".to_string(),
+            },
+            Step::AddFigure {
+                doc: PathBuf::from("./document.tex"),
+                name: "plot".to_string(),
+                style: FigureStyle::Pgfplots,
+                graphics: false,
+            },
+            Step::GitInit {
+                path: PathBuf::from("."),
+            },
+            Step::RefreshDatabase {
+                tree: PathBuf::from("/tmp/texmf"),
+            },
+        ];
+
+        let json = serde_json::to_string_pretty(&steps).unwrap();
+        let read: Vec<Step> = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string_pretty(&read).unwrap(), json);
+        assert!(json.contains("\"step\": \"write-resource\""));
+        assert!(json.contains("\"style\": \"pgfplots\""));
+        assert!(json.contains("\"step\": \"refresh-database\""));
+    }
+
+    fn write_template_step(path: &Path, overwrite: bool) -> Step {
+        Step::WriteTemplate {
+            template: None,
+            class_resource: "class/arteacle.cls".to_string(),
+            location: ResourceLocation::Remote,
+            commit: "1d72b1d".to_string(),
+            path: path.to_path_buf(),
+            overwrite,
+        }
+    }
+
+    #[test]
+    fn template_written_since_plan_not_overwritten() {
+        let path = std::env::temp_dir().join(format!("mktex-plan-{}.tex", std::process::id()));
+        fs::write(&path, "mine").unwrap();
+        let result = apply_step(&write_template_step(&path, false));
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+        assert_eq!(contents, "mine");
+    }
+
+    #[test]
+    fn template_overwritten_only_if_user_agrees() {
+        // The default prompter declines
+        let path = std::env::temp_dir().join(format!("mktex-plan-{}-ask.tex", std::process::id()));
+        fs::write(&path, "mine").unwrap();
+        let result = apply_step(&write_template_step(&path, true));
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_ok());
+        assert_eq!(contents, "mine");
+    }
+
+    #[test]
+    fn plan_with_unknown_step_rejected() {
+        let json = r#"[{"step": "delete-everything", "path": "/"}]"#;
+        assert!(serde_json::from_str::<Vec<Step>>(json).is_err());
+    }
+}
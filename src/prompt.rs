@@ -0,0 +1,50 @@
+// Ask the user what to do
+//
+// Library code does not ask questions on the terminal itself; instead, it asks
+// a prompter.  The default prompter answers every question with its default
+// answer (which never overwrites or changes anything the user has not asked
+// for), as is right when no one is there to answer, e.g., for tools using
+// mktex as a library.  The mktex programme installs one that asks on the
+// terminal.
+
+use lazy_static::lazy_static;
+use std::sync::RwLock;
+
+pub trait Prompter: Send + Sync {
+    /// Ask a yes or no question
+    fn confirm(&self, prompt: &str, default: bool) -> bool;
+
+    /// Ask the user to choose one of several options, giving the index of the
+    /// one chosen
+    fn select(&self, prompt: &str, options: &[&str], default: usize) -> usize;
+}
+
+/// The default prompter: the default answer to every question
+pub struct Defaults;
+
+impl Prompter for Defaults {
+    fn confirm(&self, _prompt: &str, default: bool) -> bool {
+        default
+    }
+
+    fn select(&self, _prompt: &str, _options: &[&str], default: usize) -> usize {
+        default
+    }
+}
+
+lazy_static! {
+    static ref PROMPTER: RwLock<Box<dyn Prompter>> = RwLock::new(Box::new(Defaults));
+}
+
+/// Replace the prompter that questions are asked with
+pub fn set_prompter(prompter: impl Prompter + 'static) {
+    *PROMPTER.write().unwrap() = Box::new(prompter);
+}
+
+pub fn confirm(prompt: &str, default: bool) -> bool {
+    PROMPTER.read().unwrap().confirm(prompt, default)
+}
+
+pub fn select(prompt: &str, options: &[&str], default: usize) -> usize {
+    PROMPTER.read().unwrap().select(prompt, options, default)
+}
@@ -0,0 +1,176 @@
+// Class and package metadata
+//
+// LaTeX classes and packages identify themselves with a line like
+//
+//     \ProvidesClass{arteacle}[2024/03/01 v1.4 Jake's article class]
+//
+// where, by convention, the optional argument holds the release date, the
+// version, and a short description, in that order.  Documents may in turn
+// request a minimum release date for their class:
+//
+//     \documentclass{arteacle}[2024/03/01]
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fmt;
+
+lazy_static! {
+    static ref PROVIDES_RE: Regex = Regex::new(
+        r"\\Provides(?P<kind>Class|Package|File)\s*\{(?P<name>[^}]+)\}\s*(?:\[(?P<info>[^\]]*)\])?"
+    )
+    .unwrap();
+    static ref DATE_RE: Regex = Regex::new(r"^\d{4}[/-]\d{2}[/-]\d{2}$").unwrap();
+    static ref VERSION_RE: Regex = Regex::new(r"^v?\d+(\.\w+)*$").unwrap();
+    static ref REQUESTED_DATE_RE: Regex = Regex::new(
+        r"\\documentclass\s*(?:\[[^\]]*\])?\s*\{(?P<class>[^}]+)\}\s*\[(?P<date>\d{4}[/-]\d{2}[/-]\d{2})\]"
+    )
+    .unwrap();
+}
+
+pub struct Provides {
+    pub name: String,
+    pub date: Option<String>,
+    pub version: Option<String>,
+    pub description: Option<String>,
+}
+
+impl fmt::Display for Provides {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for field in [&self.date, &self.version].into_iter().flatten() {
+            write!(f, " {}", field)?;
+        }
+        Ok(())
+    }
+}
+
+// Dates may be written with slashes or hyphens; normalise them so that they
+// compare correctly as strings
+fn normalise_date(date: &str) -> String {
+    date.replace('-', "/")
+}
+
+/// Parse the \ProvidesClass (or \ProvidesPackage) line of a class or package
+pub fn parse(contents: &str) -> Option<Provides> {
+    let caps = PROVIDES_RE.captures(contents)?;
+    let mut provides = Provides {
+        name: caps["name"].trim().to_string(),
+        date: None,
+        version: None,
+        description: None,
+    };
+
+    let Some(info) = caps.name("info") else {
+        return Some(provides);
+    };
+    let mut words = info.as_str().split_whitespace().peekable();
+    if let Some(word) = words.next_if(|word| DATE_RE.is_match(word)) {
+        provides.date = Some(normalise_date(word));
+    }
+    if let Some(word) = words.next_if(|word| VERSION_RE.is_match(word)) {
+        provides.version = Some(word.to_string());
+    }
+    let description = words.collect::<Vec<_>>().join(" ");
+    if !description.is_empty() {
+        provides.description = Some(description);
+    }
+
+    Some(provides)
+}
+
+/// If a document requests a newer release of its class than the one given,
+/// get the class name and the date requested
+pub fn newer_date_requested(document: &str, class: &Provides) -> Option<(String, String)> {
+    let caps = REQUESTED_DATE_RE.captures(document)?;
+    let requested = normalise_date(&caps["date"]);
+    let provided = class.date.as_ref()?;
+
+    (caps["class"].trim() == class.name && &requested > provided)
+        .then(|| (class.name.clone(), requested))
+}
+
+/// Get the name of the class a document uses, if it requests a release date
+pub fn requested_class(document: &str) -> Option<String> {
+    REQUESTED_DATE_RE
+        .captures(document)
+        .map(|caps| caps["class"].trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn class_with_date_version_and_description() {
+        let provides =
+            parse("\\NeedsTeXFormat{LaTeX2e}\n\\ProvidesClass{arteacle}[2024/03/01 v1.4 Jake's article class]\n")
+                .unwrap();
+        assert_eq!(provides.name, "arteacle");
+        assert_eq!(provides.date.as_deref(), Some("2024/03/01"));
+        assert_eq!(provides.version.as_deref(), Some("v1.4"));
+        assert_eq!(
+            provides.description.as_deref(),
+            Some("Jake's article class")
+        );
+        assert_eq!(provides.to_string(), "arteacle 2024/03/01 v1.4");
+    }
+
+    #[test]
+    fn missing_version() {
+        let provides = parse("\\ProvidesPackage{common}[2023-01-01 Common macros]").unwrap();
+        assert_eq!(provides.date.as_deref(), Some("2023/01/01"));
+        assert_eq!(provides.version, None);
+        assert_eq!(provides.description.as_deref(), Some("Common macros"));
+    }
+
+    #[test]
+    fn missing_date() {
+        let provides = parse("\\ProvidesClass{lettear}[v1.0 Letter class]").unwrap();
+        assert_eq!(provides.date, None);
+        assert_eq!(provides.version.as_deref(), Some("v1.0"));
+        assert_eq!(provides.to_string(), "lettear v1.0");
+    }
+
+    #[test]
+    fn no_optional_argument() {
+        let provides = parse("\\ProvidesClass{teamer}\n").unwrap();
+        assert_eq!(provides.name, "teamer");
+        assert!(provides.date.is_none() && provides.version.is_none());
+        assert!(parse("\\documentclass{article}").is_none());
+    }
+
+    #[test]
+    fn newer_date_requested_compared() {
+        let class = parse("\\ProvidesClass{arteacle}[2024/03/01 v1.4]").unwrap();
+        assert_eq!(
+            newer_date_requested("\\documentclass[11pt]{arteacle}[2024-06-01]", &class),
+            Some(("arteacle".to_string(), "2024/06/01".to_string()))
+        );
+        assert_eq!(
+            newer_date_requested("\\documentclass{arteacle}[2024/03/01]", &class),
+            None
+        );
+        assert_eq!(
+            newer_date_requested("\\documentclass{arteacle}[2023/12/31]", &class),
+            None
+        );
+        // Only the document's own class is compared
+        assert_eq!(
+            newer_date_requested("\\documentclass{lettear}[2025/01/01]", &class),
+            None
+        );
+    }
+
+    #[test]
+    fn requested_date_unknown_for_undated_class() {
+        let class = parse("\\ProvidesClass{arteacle}[v1.4]").unwrap();
+        assert_eq!(
+            newer_date_requested("\\documentclass{arteacle}[2024/06/01]", &class),
+            None
+        );
+        assert_eq!(
+            requested_class("\\documentclass{arteacle}[2024/06/01]").as_deref(),
+            Some("arteacle")
+        );
+    }
+}
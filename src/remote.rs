@@ -1,62 +1,578 @@
 // Fetch resource remotely
-use super::config::*;
+//
+// Every request goes through send, which gives up on requests that take too
+// long, and retries those that failed for a transient reason (a timeout, a
+// dropped connection, or a server error), waiting twice as long before each
+// retry.  When GitHub's rate limit is hit (60 requests an hour without
+// authentication), the X-RateLimit-* and Retry-After headers of the response
+// say when requests will be accepted again: short waits are waited out, and
+// otherwise the error says exactly when to try again.
+//
+// Requests go through a proxy and trust extra root certificates if the
+// network settings say so, and resource files are fetched from the configured
+// mirrors of the resource repository (in order) before GitHub itself.
+//
+// GET requests are conditional on the copy in the on-disk cache (see the cache
+// module), so that unchanged responses are not downloaded again.
+use super::{cache, config::*, report, settings::SETTINGS};
+use chrono::prelude::*;
+use lazy_static::lazy_static;
+use reqwest::{
+    Certificate, NoProxy, Proxy, StatusCode,
+    blocking::{Client, RequestBuilder, Response},
+    header::{ACCEPT, ETAG, HeaderMap, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RANGE},
+};
+use std::{env, fs, io::Read, str::FromStr, thread, time::Duration};
 
-// use "master" for tag
-pub fn get_remote_resource(resource: &str, tag: &str) -> String {
-    let uri = format!(
-        "https://raw.githubusercontent.com/{}/{}/{}/{}",
-        GITHUB_USER, GITHUB_REPO_NAME, tag, resource,
-    );
-    reqwest::blocking::get(uri)
-        .expect("Cannot get remote resource")
-        .text()
-        .expect("Cannot get text from remote response")
+lazy_static! {
+    // Shared by every request (including from several threads at once), so
+    // that connections to GitHub are reused
+    static ref CLIENT: Result<Client, String> = build_client();
+}
+
+fn client() -> Result<&'static Client, String> {
+    CLIENT
+        .as_ref()
+        .map_err(|e| format!("Cannot set up HTTP client: {}", e))
+}
+
+fn build_client() -> Result<Client, String> {
+    let settings = &SETTINGS.network;
+    let mut builder = Client::builder()
+        .user_agent("mktex.rs")
+        .timeout(Duration::from_secs(settings.timeout))
+        .connect_timeout(Duration::from_secs(settings.connect_timeout));
+
+    // Without a proxy in the settings, reqwest uses the one from the
+    // environment (if any), but then only applies NO_PROXY from the
+    // environment to it.  So if there are hosts in the settings to reach
+    // without a proxy, we set up the proxies from the environment ourselves
+    if let Some(proxy) = &settings.proxy {
+        let proxy = Proxy::all(proxy)
+            .map_err(|e| format!("invalid proxy {:?}: {}", proxy, e))?
+            .no_proxy(settings.no_proxy.as_deref().and_then(NoProxy::from_string));
+        builder = builder.proxy(proxy);
+    } else if let Some(no_proxy) = &settings.no_proxy {
+        let no_proxy = match env_var(&["no_proxy", "NO_PROXY"]) {
+            Some(env_no_proxy) => format!("{},{}", no_proxy, env_no_proxy),
+            None => no_proxy.clone(),
+        };
+        let invalid = |proxy: &str, e: reqwest::Error| format!("invalid proxy {:?}: {}", proxy, e);
+        if let Some(proxy) = env_var(&["http_proxy", "HTTP_PROXY"]) {
+            let proxy = Proxy::http(&proxy).map_err(|e| invalid(&proxy, e))?;
+            builder = builder.proxy(proxy.no_proxy(NoProxy::from_string(&no_proxy)));
+        }
+        if let Some(proxy) = env_var(&["https_proxy", "HTTPS_PROXY"]) {
+            let proxy = Proxy::https(&proxy).map_err(|e| invalid(&proxy, e))?;
+            builder = builder.proxy(proxy.no_proxy(NoProxy::from_string(&no_proxy)));
+        }
+        if let Some(proxy) = env_var(&["all_proxy", "ALL_PROXY"]) {
+            let proxy = Proxy::all(&proxy).map_err(|e| invalid(&proxy, e))?;
+            builder = builder.proxy(proxy.no_proxy(NoProxy::from_string(&no_proxy)));
+        }
+    }
+
+    if let Some(path) = &settings.ca_bundle {
+        let pem = fs::read(path).map_err(|e| format!("cannot read {:?}: {}", path, e))?;
+        let certificates = Certificate::from_pem_bundle(&pem)
+            .map_err(|e| format!("invalid certificate bundle {:?}: {}", path, e))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    builder.build().map_err(|e| e.to_string())
+}
+
+// The first of the given environment variables that is set (and not empty)
+fn env_var(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+}
+
+/// Base URLs to fetch resource files from, in the order to try them: each
+/// mirror, then GitHub
+fn raw_base_urls() -> Vec<String> {
+    SETTINGS
+        .network
+        .mirrors
+        .iter()
+        .map(|mirror| {
+            mirror
+                .replace("{user}", GITHUB_USER)
+                .replace("{repo}", GITHUB_REPO_NAME)
+                .trim_end_matches('/')
+                .to_string()
+        })
+        .chain([format!(
+            "https://raw.githubusercontent.com/{}/{}",
+            GITHUB_USER, GITHUB_REPO_NAME
+        )])
+        .collect()
+}
+
+/// Get a resource file at the given ref from each base URL in turn, until one
+/// has it.  If none do, the last response (or error) is returned
+fn get_raw(resource: &str, tag: &str) -> Result<Fetched, String> {
+    let mut result = Err("No base URLs to fetch from".to_string());
+    for base in raw_base_urls() {
+        let uri = format!("{}/{}/{}", base, tag, resource);
+        result = get(uri, "*/*", Offline::UseCached);
+        match &result {
+            Ok(fetched) if fetched.status.is_success() => break,
+            Ok(fetched) => report::debug(format!(
+                "Cannot get {:?} from {}: HTTP {}",
+                resource, base, fetched.status
+            )),
+            Err(e) => report::warn(format!("Cannot get {:?} from {}: {}", resource, base, e)),
+        }
+    }
+    result
+}
+
+/// The status and body of a response to a GET request
+struct Fetched {
+    status: StatusCode,
+    body: String,
+}
+
+/// What to do when the server cannot be reached but a response is cached
+#[derive(Clone, Copy, PartialEq)]
+enum Offline {
+    /// Use the cached copy, for responses that cannot change (e.g., a file at
+    /// a given commit)
+    UseCached,
+    /// Fail, for responses that change over time (e.g., which commit a branch
+    /// points to), which an old copy would misrepresent
+    Fail,
+}
+
+/// Send a GET request, conditional on the cached copy of its response (if
+/// any).  The cached copy is used if the server says that it has not changed,
+/// or (if allowed) if the server cannot be reached
+fn get(uri: String, accept: &str, offline: Offline) -> Result<Fetched, String> {
+    // The same URL may give different responses for different media types
+    let key = format!("{} {}", accept, uri);
+    let cached = match SETTINGS.network.cache {
+        true => cache::lookup(&key),
+        false => None,
+    };
+
+    let mut request = client()?.get(&uri).header(ACCEPT, accept);
+    if let Some(cached) = &cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = match (send(request), cached) {
+        (Ok(response), Some(cached)) if response.status() == StatusCode::NOT_MODIFIED => {
+            report::debug(format!("Using cached copy of {} (not modified)", uri));
+            return Ok(Fetched {
+                status: StatusCode::OK,
+                body: cached.body,
+            });
+        }
+        (Ok(response), _) => response,
+        (Err(e), Some(cached)) if offline == Offline::UseCached => {
+            report::warn(format!("{}; using cached copy of {}", e, uri));
+            return Ok(Fetched {
+                status: StatusCode::OK,
+                body: cached.body,
+            });
+        }
+        (Err(e), _) => return Err(e),
+    };
+
+    let status = response.status();
+    let etag = header::<String>(response.headers(), ETAG.as_str());
+    let last_modified = header::<String>(response.headers(), LAST_MODIFIED.as_str());
+    let body = response.text().map_err(|e| e.to_string())?;
+    if SETTINGS.network.cache && status.is_success() && (etag.is_some() || last_modified.is_some())
+    {
+        cache::store(&cache::Entry {
+            key,
+            etag,
+            last_modified,
+            body: body.clone(),
+        });
+    }
+
+    Ok(Fetched { status, body })
+}
+
+/// Send a request, retrying it if it fails for a transient reason.
+///
+/// Responses with an error status are returned as they are (for the caller to
+/// handle), unless they say that a rate limit was hit, or are server errors
+/// that persist after every retry.
+fn send(request: RequestBuilder) -> Result<Response, String> {
+    let retries = SETTINGS.network.retries;
+    let max_retry_after = Duration::from_secs(SETTINGS.network.max_retry_after);
+    let mut backoff = Duration::from_millis(SETTINGS.network.backoff);
+
+    let mut attempt = 0;
+    loop {
+        let out_of_retries = attempt >= retries;
+        let request = request
+            .try_clone()
+            .ok_or("Cannot retry a request with a streamed body")?;
+        let (reason, wait) = match request.send() {
+            Ok(response) => {
+                let headers = response.headers();
+                if let Some(remaining) = header::<u64>(headers, "x-ratelimit-remaining") {
+                    report::debug(format!("{} GitHub API requests remaining", remaining));
+                }
+                match rate_limited(response.status(), headers, response.url().host_str()) {
+                    Some((reason, Some(wait))) if !out_of_retries && wait <= max_retry_after => {
+                        (reason, wait)
+                    }
+                    Some((reason, _)) => return Err(reason),
+                    None if response.status().is_server_error() => {
+                        let reason = format!("HTTP {} from {}", response.status(), response.url());
+                        if out_of_retries {
+                            return Err(gave_up(reason, attempt));
+                        }
+                        (reason, backoff)
+                    }
+                    None => return Ok(response),
+                }
+            }
+            // Other errors (e.g., an invalid URL, or a redirect loop) would
+            // only happen again
+            Err(e) if e.is_timeout() || e.is_connect() => {
+                if out_of_retries {
+                    return Err(gave_up(e.to_string(), attempt));
+                }
+                (e.to_string(), backoff)
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+
+        attempt += 1;
+        report::warn(format!(
+            "{}; retrying in {:.1}s (retry {} of {})",
+            reason,
+            wait.as_secs_f64(),
+            attempt,
+            retries
+        ));
+        thread::sleep(wait);
+        backoff *= 2;
+    }
+}
+
+fn gave_up(reason: String, retries: u32) -> String {
+    match retries {
+        0 => reason,
+        _ => format!("{} (gave up after {} retries)", reason, retries),
+    }
+}
+
+fn header<T: FromStr>(headers: &HeaderMap, name: &str) -> Option<T> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// If a response (with the given status and headers, from the given host) says
+/// that a rate limit was hit, say so, and how long until requests will be
+/// accepted again (if the response says).
+///
+/// See https://docs.github.com/en/rest/using-the-rest-api/rate-limits-for-the-rest-api
+fn rate_limited(
+    status: StatusCode,
+    headers: &HeaderMap,
+    host: Option<&str>,
+) -> Option<(String, Option<Duration>)> {
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+
+    let retry_after = header::<u64>(headers, "retry-after").map(Duration::from_secs);
+    let remaining = header::<u64>(headers, "x-ratelimit-remaining");
+    if retry_after.is_none() && remaining != Some(0) && status != StatusCode::TOO_MANY_REQUESTS {
+        // Forbidden for some other reason
+        return None;
+    }
+
+    let limit = match header::<u64>(headers, "x-ratelimit-limit") {
+        Some(limit) => format!("GitHub API rate limit of {} requests per hour", limit),
+        None => format!("Rate limit of {}", host.unwrap_or("server")),
+    };
+    // Secondary rate limits give Retry-After; the primary limit gives the
+    // time it resets at instead
+    let wait = retry_after.or_else(|| {
+        let reset = header::<i64>(headers, "x-ratelimit-reset")?;
+        Some(Duration::from_secs(
+            (reset - Utc::now().timestamp()).max(0) as u64
+        ))
+    });
+
+    let reason = match wait {
+        Some(wait) => {
+            let at = Local::now() + chrono::Duration::from_std(wait).unwrap_or_default();
+            format!(
+                "{} exceeded; try again at {} (in {}), or use --local",
+                limit,
+                at.format("%H:%M:%S"),
+                humanize(wait)
+            )
+        }
+        None => format!("{} exceeded; try again later, or use --local", limit),
+    };
+    Some((reason, wait))
+}
+
+fn humanize(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..60 => format!("{} seconds", secs),
+        60..120 => "1 minute".to_string(),
+        _ => format!("{} minutes", secs.div_ceil(60)),
+    }
+}
+
+/// Get a resource file at the given ref (a full commit hash), or None if the
+/// resource does not exist
+pub fn try_get_remote_resource(resource: &str, tag: &str) -> Result<Option<String>, String> {
+    let fetched = get_raw(resource, tag)?;
+    if fetched.status == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !fetched.status.is_success() {
+        return Err(format!("HTTP {}", fetched.status));
+    }
+
+    Ok(Some(fetched.body))
+}
+
+/// Get (at most) the first `len` bytes of a resource file at the given ref,
+/// without downloading the rest of it.  Returns None if the resource does not
+/// exist
+pub fn get_remote_resource_head(
+    resource: &str,
+    tag: &str,
+    len: usize,
+) -> Result<Option<Vec<u8>>, String> {
+    let mut result = Ok(None);
+    for base in raw_base_urls() {
+        let uri = format!("{}/{}/{}", base, tag, resource);
+        let request = client()?
+            .get(&uri)
+            .header(RANGE, format!("bytes=0-{}", len - 1));
+        match send(request) {
+            // Servers that do not support ranges send the whole file, so stop
+            // reading after the first bytes ourselves
+            Ok(response) if response.status().is_success() => {
+                let mut head = Vec::new();
+                response
+                    .take(len as u64)
+                    .read_to_end(&mut head)
+                    .map_err(|e| e.to_string())?;
+                return Ok(Some(head));
+            }
+            Ok(response) => report::debug(format!(
+                "Cannot get {:?} from {}: HTTP {}",
+                resource,
+                base,
+                response.status()
+            )),
+            Err(e) => {
+                report::warn(format!("Cannot get {:?} from {}: {}", resource, base, e));
+                result = Err(e);
+            }
+        }
+    }
+    result
 }
 
 /// Get latest commit hash (SHA1 ID) from the remote repository.
 ///
 /// This method uses GitHub's repo API to fetch HEAD information at
 /// the main branch (in this case, master).
-pub fn latest_commit_hash() -> String {
+pub fn latest_full_commit_hash() -> Result<String, String> {
     // https://docs.github.com/en/rest/git/refs?apiVersion=2022-11-28
     let uri = format!(
         "https://api.github.com/repos/{}/{}/git/ref/heads/{}",
         GITHUB_USER, GITHUB_REPO_NAME, MAIN_BRANCH,
     );
 
-    let client = reqwest::blocking::Client::new();
-    let mut headers = reqwest::header::HeaderMap::new();
+    let body = api_get(uri, "application/json", Offline::Fail)?;
+    let commit_data_raw: serde_json::Value =
+        serde_json::from_str(&body).map_err(|e| e.to_string())?;
+    commit_data_raw
+        .get("object")
+        .and_then(|object| object.get("sha"))
+        .and_then(|sha| sha.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| "Cannot get commit hash from response".to_string())
+}
 
-    // https://docs.github.com/en/rest/overview/resources-in-the-rest-api#user-agent-required
-    headers.append("accept", "application/json".parse().unwrap());
-    headers.append("user-agent", "mktex.rs".parse().unwrap());
+fn api_get(uri: String, accept: &str, offline: Offline) -> Result<String, String> {
+    let fetched = get(uri, accept, offline)?;
+    if !fetched.status.is_success() {
+        return Err(format!("HTTP {}", fetched.status));
+    }
 
-    let request = client.get(uri).headers(headers);
+    Ok(fetched.body)
+}
 
-    let body = request
-        .send()
-        .expect("Cannot get response from GitHub API")
-        .text()
-        .expect("Cannot get text from remote response");
+/// Resolve a (possibly abbreviated) commit hash or ref to a full commit hash.
+///
+/// See https://docs.github.com/en/rest/commits/commits?apiVersion=2022-11-28#get-a-commit
+pub fn resolve_commit(rev: &str) -> Result<String, String> {
+    let uri = format!(
+        "https://api.github.com/repos/{}/{}/commits/{}",
+        GITHUB_USER, GITHUB_REPO_NAME, rev,
+    );
 
-    let commit_data_raw: serde_json::Value =
-        serde_json::from_str(&body).expect("The JSON response was not well defined");
-    let latest_commit = commit_data_raw
-        .as_object()
-        .expect("Cannot parse response as object")
-        .get("object")
-        .expect("Cannot get latest object information from remote ref")
-        .get("sha")
-        .expect("Cannot get commit hash from response")
-        .as_str()
-        .expect("Cannot parse commit hash as str")
-        .to_string();
+    // A (full or abbreviated) commit hash always resolves to the same commit,
+    // but a branch or tag may move
+    let offline = match rev.chars().all(|c| c.is_ascii_hexdigit()) {
+        true => Offline::UseCached,
+        false => Offline::Fail,
+    };
+    // This media type returns just the SHA as plain text
+    api_get(uri, "application/vnd.github.sha", offline).map(|sha| sha.trim().to_string())
+}
+
+/// Commits (abbreviated hash and summary) and changed files between two refs
+pub type Changes = (Vec<(String, String)>, Vec<String>);
 
-    // https://stackoverflow.com/a/38461750/
-    match latest_commit.char_indices().nth(SHORT_HASH_LENGTH) {
-        None => latest_commit,
-        Some((idx, _)) => (latest_commit[..idx]).to_string(),
+/// Commits and changed files between two refs.
+///
+/// See https://docs.github.com/en/rest/commits/commits?apiVersion=2022-11-28#compare-two-commits
+pub fn compare(base: &str, head: &str) -> Result<Changes, String> {
+    let uri = format!(
+        "https://api.github.com/repos/{}/{}/compare/{}...{}",
+        GITHUB_USER, GITHUB_REPO_NAME, base, head,
+    );
+    let body = api_get(uri, "application/json", Offline::Fail)?;
+    let data: serde_json::Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+
+    let commits = data
+        .get("commits")
+        .and_then(|commits| commits.as_array())
+        .map(|commits| {
+            commits
+                .iter()
+                .filter_map(|commit| {
+                    let sha = commit.get("sha")?.as_str()?;
+                    let message = commit.get("commit")?.get("message")?.as_str()?;
+                    Some((
+                        sha.chars().take(SHORT_HASH_LENGTH).collect(),
+                        message.lines().next().unwrap_or_default().to_string(),
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let files = data
+        .get("files")
+        .and_then(|files| files.as_array())
+        .map(|files| {
+            files
+                .iter()
+                .filter_map(|file| file.get("filename")?.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok((commits, files))
+}
+
+/// List every file in the remote repository at the given ref.
+///
+/// This uses GitHub's Git trees API.  See
+/// https://docs.github.com/en/rest/git/trees?apiVersion=2022-11-28
+pub fn list_remote_files(tag: &str) -> Result<Vec<String>, String> {
+    let uri = format!(
+        "https://api.github.com/repos/{}/{}/git/trees/{}?recursive=1",
+        GITHUB_USER, GITHUB_REPO_NAME, tag,
+    );
+    let body = api_get(uri, "application/json", Offline::UseCached)?;
+    let data: serde_json::Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+    let tree = data
+        .get("tree")
+        .and_then(|tree| tree.as_array())
+        .ok_or_else(|| "Cannot get tree from response".to_string())?;
+
+    Ok(tree
+        .iter()
+        .filter(|entry| entry.get("type").and_then(|t| t.as_str()) == Some("blob"))
+        .filter_map(|entry| entry.get("path").and_then(|p| p.as_str()))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Get a BibTeX entry for a DOI using content negotiation.
+///
+/// See https://citation.crosscite.org/docs.html
+pub fn bibtex_from_doi(doi: &str) -> Result<String, String> {
+    let uri = format!("https://doi.org/{}", doi);
+    api_get(uri, "application/x-bibtex", Offline::UseCached)
+}
+
+/// Check whether a resource can be fetched from the remote repository
+pub fn remote_resource_reachable(resource: &str, tag: &str) -> Result<(), String> {
+    // Not from the cache: the point is to check that the network works
+    let mut result = Err("No base URLs to fetch from".to_string());
+    for base in raw_base_urls() {
+        let uri = format!("{}/{}/{}", base, tag, resource);
+        result = send(client()?.head(uri)).and_then(|response| {
+            if response.status().is_success() {
+                Ok(())
+            } else {
+                Err(format!("HTTP {}", response.status()))
+            }
+        });
+        if result.is_ok() {
+            break;
+        }
     }
+    result
+}
+
+/// Rate limit status of the GitHub API
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    /// Time at which the limit resets, in seconds since the Unix epoch
+    pub reset: i64,
+}
+
+/// Get the current rate limit status of the GitHub API.
+///
+/// Requests to this endpoint do not count against the rate limit.
+pub fn rate_limit() -> Result<RateLimit, String> {
+    // https://docs.github.com/en/rest/rate-limit/rate-limit?apiVersion=2022-11-28
+    let uri = "https://api.github.com/rate_limit";
+
+    // The shared client sends the User-Agent header that the API requires
+    let body = send(client()?.get(uri))?
+        .text()
+        .map_err(|e| e.to_string())?;
+
+    let data: serde_json::Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+    let core = data
+        .get("resources")
+        .and_then(|resources| resources.get("core"))
+        .ok_or_else(|| "Cannot get core rate limit from response".to_string())?;
+    let field = |name: &str| {
+        core.get(name)
+            .and_then(|value| value.as_i64())
+            .ok_or_else(|| format!("Cannot get {} from rate limit response", name))
+    };
+
+    Ok(RateLimit {
+        limit: field("limit")? as u64,
+        remaining: field("remaining")? as u64,
+        reset: field("reset")?,
+    })
 }
 
 /*
@@ -180,3 +696,62 @@ pub fn get_latest_commit_hash_gitoxide() -> String {
     unimplemented!()
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn not_rate_limited() {
+        let headers = headers(&[("x-ratelimit-remaining", "59")]);
+        assert!(rate_limited(StatusCode::OK, &headers, None).is_none());
+        assert!(rate_limited(StatusCode::FORBIDDEN, &headers, None).is_none());
+    }
+
+    #[test]
+    fn primary_rate_limit() {
+        let reset = Utc::now().timestamp() + 600;
+        let headers = headers(&[
+            ("x-ratelimit-limit", "60"),
+            ("x-ratelimit-remaining", "0"),
+            ("x-ratelimit-reset", &reset.to_string()),
+        ]);
+        let (reason, wait) =
+            rate_limited(StatusCode::FORBIDDEN, &headers, Some("api.github.com")).unwrap();
+        assert!(reason.starts_with("GitHub API rate limit of 60 requests per hour exceeded"));
+        assert!(wait.unwrap() > Duration::from_secs(590));
+    }
+
+    #[test]
+    fn secondary_rate_limit() {
+        let headers = headers(&[("retry-after", "30")]);
+        let (reason, wait) = rate_limited(
+            StatusCode::TOO_MANY_REQUESTS,
+            &headers,
+            Some("mirror.example"),
+        )
+        .unwrap();
+        assert!(reason.starts_with("Rate limit of mirror.example exceeded"));
+        assert!(reason.contains("(in 30 seconds)"));
+        assert_eq!(wait, Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn rate_limit_without_reset() {
+        let headers = headers(&[]);
+        let (reason, wait) = rate_limited(StatusCode::TOO_MANY_REQUESTS, &headers, None).unwrap();
+        assert_eq!(
+            reason,
+            "Rate limit of server exceeded; try again later, or use --local"
+        );
+        assert_eq!(wait, None);
+    }
+}
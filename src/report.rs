@@ -0,0 +1,224 @@
+// Report what mktex is doing
+//
+// Library code does not print anything itself; instead, it reports events to a
+// reporter.  The default reporter prints progress messages to standard error,
+// e.g.,
+//
+//     [INFO] Writing template "templates/arteacle.tex" to "./document.tex"
+//
+// and the output of commands (such as the list of resources), which is
+// reported at the Output level, as it is to standard output.  Tools using
+// mktex as a library can install their own reporter to show (or ignore) events
+// however they like.  Events for things done to files and resources also carry
+// the action taken and what it was taken on, so that they can be logged as
+// JSON, one object per line:
+//
+//     {"time":"...","level":"info","action":"write","target":"./document.tex","message":"..."}
+
+use chrono::prelude::*;
+use clap::ValueEnum;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::{fmt, sync::RwLock};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    /// The output of a command, rather than a message about what is being
+    /// done; it is never left out
+    Output,
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Level::Output => "OUTPUT",
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What was done to a file or resource
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    /// A resource was fetched from the resource repository
+    Fetch,
+    /// A file or directory was written
+    Write,
+    /// A file was left alone (e.g., because it already exists, or on a dry run)
+    Skip,
+    /// The user was asked what to do
+    Prompt,
+    /// An installed resource differs from the resource repository
+    SyncMismatch,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Lines like "[INFO] message"
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(Serialize)]
+pub struct Event {
+    pub time: String,
+    pub level: Level,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<Action>,
+    /// File or resource that the action was taken on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub message: String,
+}
+
+pub type Reporter = Box<dyn Fn(&Event) + Send + Sync>;
+
+lazy_static! {
+    static ref REPORTER: RwLock<Reporter> = RwLock::new(Box::new(print_event));
+}
+
+// Whether to show an event when showing events of the given level or more
+// severe.  The output of commands is always shown
+fn shown(level: Level, max_level: Level) -> bool {
+    level == Level::Output || level <= max_level
+}
+
+/// The default reporter: information, warnings, and errors to standard error,
+/// so that standard output only holds the output of commands
+pub fn print_event(event: &Event) {
+    if event.level == Level::Output {
+        println!("{}", event.message);
+    } else if shown(event.level, Level::Info) {
+        eprintln!("[{}] {}", event.level, event.message);
+    }
+}
+
+/// A reporter that prints events of the given level or more severe, in the
+/// given format, to standard error (and output to standard output)
+pub fn logger(max_level: Level, format: LogFormat) -> impl Fn(&Event) + Send + Sync {
+    move |event: &Event| {
+        if event.level == Level::Output {
+            println!("{}", event.message);
+            return;
+        }
+        if !shown(event.level, max_level) {
+            return;
+        }
+        match format {
+            LogFormat::Text => eprintln!("[{}] {}", event.level, event.message),
+            LogFormat::Json => eprintln!("{}", serde_json::to_string(event).unwrap()),
+        }
+    }
+}
+
+/// Replace the reporter that events are sent to
+pub fn set_reporter(reporter: impl Fn(&Event) + Send + Sync + 'static) {
+    *REPORTER.write().unwrap() = Box::new(reporter);
+}
+
+fn send(level: Level, action: Option<Action>, target: Option<String>, message: String) {
+    let event = Event {
+        time: Local::now().to_rfc3339(),
+        level,
+        action,
+        target,
+        message,
+    };
+    (REPORTER.read().unwrap())(&event);
+}
+
+pub fn report(level: Level, message: impl Into<String>) {
+    send(level, None, None, message.into());
+}
+
+/// Report an action taken on a file or resource
+pub fn action(level: Level, action: Action, target: impl fmt::Display, message: impl Into<String>) {
+    send(
+        level,
+        Some(action),
+        Some(target.to_string()),
+        message.into(),
+    );
+}
+
+/// Report (a line of) the output of a command
+pub fn output(message: impl Into<String>) {
+    report(Level::Output, message);
+}
+
+pub fn debug(message: impl Into<String>) {
+    report(Level::Debug, message);
+}
+
+pub fn info(message: impl Into<String>) {
+    report(Level::Info, message);
+}
+
+pub fn warn(message: impl Into<String>) {
+    report(Level::Warn, message);
+}
+
+pub fn error(message: impl Into<String>) {
+    report(Level::Error, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn more_severe_levels_shown() {
+        assert!(shown(Level::Error, Level::Warn));
+        assert!(shown(Level::Warn, Level::Warn));
+        assert!(!shown(Level::Info, Level::Warn));
+        assert!(!shown(Level::Debug, Level::Info));
+        assert!(shown(Level::Debug, Level::Debug));
+    }
+
+    #[test]
+    fn output_always_shown() {
+        assert!(shown(Level::Output, Level::Error));
+    }
+
+    #[test]
+    fn action_event_as_json() {
+        let event = Event {
+            time: "2024-03-01T12:00:00+00:00".to_string(),
+            level: Level::Info,
+            action: Some(Action::SyncMismatch),
+            target: Some("./document.tex".to_string()),
+            message: "Writing".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"time":"2024-03-01T12:00:00+00:00","level":"info","action":"sync-mismatch","target":"./document.tex","message":"Writing"}"#
+        );
+    }
+
+    #[test]
+    fn plain_event_as_json() {
+        let event = Event {
+            time: "2024-03-01T12:00:00+00:00".to_string(),
+            level: Level::Warn,
+            action: None,
+            target: None,
+            message: "Careful".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"time":"2024-03-01T12:00:00+00:00","level":"warn","message":"Careful"}"#
+        );
+    }
+}
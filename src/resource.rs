@@ -1,36 +1,459 @@
 // Fetch resource!
-use super::{config, local, remote};
-use std::{fs, path::Path};
+//
+// Each resource is only fetched once per run: fetched resources are kept in
+// memory, so that, e.g., the class can be read to find the files it \inputs
+// and then installed without fetching it again.  To save waiting on one
+// request after another, everything a document needs can be fetched in
+// parallel up front with prefetch.
+//
+// Remote resources are all fetched at the same commit: the main branch is
+// resolved to a commit once per run (the run's snapshot), and documents are
+// stamped with that same commit, so that a push landing mid-run cannot mix
+// files from two versions of the repository.  Local resources are read from
+// the checkout as it is, and stamped with its HEAD commit, unless they are read
+// from git's objects at a given ref (see the local module), in which case they
+// are also all read at the commit that ref points to when first asked.
+use super::{
+    config, local, remote,
+    report::{self, Action, Level},
+};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+};
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ResourceLocation {
     Local,
     Remote,
 }
 
-pub fn fetch_resource(resource: &str, loc: &ResourceLocation) -> String {
+/// The repository that resources come from, which (with the layout settings)
+/// decides where they are installed, so that resources from different
+/// repositories do not overwrite each other
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Source {
+    pub user: String,
+    pub repo: String,
+}
+
+impl Source {
+    /// The repository that resources at a location come from: the remote
+    /// repository (also when fetched from its mirrors, which serve the same
+    /// repository), or whichever repository the local checkout was cloned
+    /// from
+    pub fn of(loc: &ResourceLocation) -> Self {
+        let remote = || Source {
+            user: config::GITHUB_USER.to_string(),
+            repo: config::GITHUB_REPO_NAME.to_string(),
+        };
+        match loc {
+            ResourceLocation::Remote => remote(),
+            ResourceLocation::Local => local::origin()
+                .map(|(user, repo)| Source { user, repo })
+                .unwrap_or_else(remote),
+        }
+    }
+}
+
+lazy_static! {
+    // Resources fetched so far (None if they do not exist), by the commit they
+    // were fetched at, so that pinning the snapshot to another commit does not
+    // give resources fetched before
+    static ref FETCHED: Mutex<HashMap<(String, String), Option<String>>> =
+        Mutex::new(HashMap::new());
+    static ref SNAPSHOT: Mutex<HashMap<ResourceLocation, String>> =
+        Mutex::new(HashMap::new());
+}
+
+fn fetched(resource: &str, snapshot: &str) -> Option<Option<String>> {
+    let key = (
+        snapshot.to_string(),
+        strip_repo_prefix(resource).to_string(),
+    );
+    FETCHED.lock().unwrap().get(&key).cloned()
+}
+
+fn remember(resource: &str, snapshot: &str, contents: Option<String>) {
+    let key = (
+        snapshot.to_string(),
+        strip_repo_prefix(resource).to_string(),
+    );
+    FETCHED.lock().unwrap().insert(key, contents);
+}
+
+/// Fetch a resource that must exist, such as a class
+pub fn fetch_resource(resource: &str, loc: &ResourceLocation) -> Result<String, String> {
+    try_fetch_resource(resource, loc)?.ok_or_else(|| match loc {
+        ResourceLocation::Local => format!(
+            "No resource {:?} in the local repository",
+            strip_repo_prefix(resource)
+        ),
+        ResourceLocation::Remote => format!(
+            "No resource {:?} in the remote repository",
+            strip_repo_prefix(resource)
+        ),
+    })
+}
+
+/// Fetch a resource that may not exist, such as an optional template.  Fails
+/// if the resource repository cannot be read at all
+pub fn try_fetch_resource(
+    resource: &str,
+    loc: &ResourceLocation,
+) -> Result<Option<String>, String> {
+    let snapshot = snapshot(loc)?;
+    if let Some(contents) = fetched(resource, &snapshot) {
+        return Ok(contents);
+    }
+
+    report_fetch(resource, loc);
+    let contents = match loc {
+        ResourceLocation::Local => read_local(resource, &snapshot)?,
+        ResourceLocation::Remote => {
+            remote::try_get_remote_resource(strip_repo_prefix(resource), &snapshot)
+                .map_err(|e| format!("Cannot get remote resource {:?}: {}", resource, e))?
+        }
+    };
+    remember(resource, &snapshot, contents.clone());
+    Ok(contents)
+}
+
+/// Fetch (at most) the first HEAD_LENGTH bytes of a resource, which is enough
+/// to find its \Provides line or \documentclass without fetching all of it.
+/// Gives None if the resource cannot be read, or is not text
+pub(crate) fn fetch_resource_head(
+    resource: &str,
+    loc: &ResourceLocation,
+) -> Result<Option<String>, String> {
+    let resource = strip_repo_prefix(resource);
+    let snapshot = snapshot(loc)?;
+    let head = match loc {
+        ResourceLocation::Local if local::reads_from_objects() => {
+            let commit_hash = snapshot
+                .strip_suffix(config::DIRTY_SUFFIX)
+                .unwrap_or(&snapshot);
+            local::local_resource_bytes_at(commit_hash, resource).map(|mut contents| {
+                contents.truncate(config::HEAD_LENGTH);
+                contents
+            })
+        }
+        ResourceLocation::Local => {
+            let mut head = Vec::new();
+            fs::File::open(local_resource_file(resource)?)
+                .and_then(|file| file.take(config::HEAD_LENGTH as u64).read_to_end(&mut head))
+                .ok()
+                .map(|_| head)
+        }
+        ResourceLocation::Remote => {
+            match remote::get_remote_resource_head(resource, &snapshot, config::HEAD_LENGTH) {
+                Ok(head) => head,
+                Err(e) => {
+                    report::warn(format!("Cannot get remote resource {:?}: {}", resource, e));
+                    None
+                }
+            }
+        }
+    };
+    Ok(head.and_then(head_text))
+}
+
+// The start of a file as text, if it is text.  The last character may have
+// been cut off partway through, in which case it is dropped
+fn head_text(mut head: Vec<u8>) -> Option<String> {
+    match String::from_utf8(head.clone()) {
+        Ok(text) => Some(text),
+        Err(e) if e.utf8_error().error_len().is_none() => {
+            head.truncate(e.utf8_error().valid_up_to());
+            String::from_utf8(head).ok()
+        }
+        Err(_) => None,
+    }
+}
+
+/// Fetch several resources in parallel, so that later fetches of them are
+/// immediate.  Resources that cannot be fetched are left for the later fetch
+/// to report
+pub(crate) fn prefetch(resources: &[String], loc: &ResourceLocation) -> Result<(), String> {
+    // Every fetch needs to know which commit to fetch at
+    let snapshot = snapshot(loc)?;
+    let resources = unfetched(resources, &snapshot);
+
+    thread::scope(|scope| {
+        // Don't open too many connections at once (e.g., when listing the
+        // whole repository)
+        for chunk in resources.chunks(config::MAX_PARALLEL_FETCHES) {
+            let handles: Vec<_> = chunk
+                .iter()
+                .map(|resource| scope.spawn(move || try_fetch_resource(resource, loc)))
+                .collect();
+            for handle in handles {
+                let _ = handle.join().unwrap();
+            }
+        }
+    });
+    Ok(())
+}
+
+// The resources not yet fetched at the snapshot
+fn unfetched<'a>(resources: &'a [String], snapshot: &str) -> Vec<&'a String> {
+    resources
+        .iter()
+        .filter(|resource| fetched(resource, snapshot).is_none())
+        .collect()
+}
+
+/// Forget resources fetched so far, e.g., because the local checkout has
+/// changed since
+pub(crate) fn forget_fetched() {
+    FETCHED.lock().unwrap().clear();
+    SNAPSHOT.lock().unwrap().clear();
+}
+
+/// The commit that resources are fetched at this run: for the remote
+/// repository, the full hash of the latest commit on the main branch when
+/// first asked; for the local checkout, its HEAD commit (with DIRTY_SUFFIX if
+/// resources are read from a working tree with uncommitted changes)
+pub fn snapshot(loc: &ResourceLocation) -> Result<String, String> {
+    if let Some(commit_hash) = SNAPSHOT.lock().unwrap().get(loc) {
+        return Ok(commit_hash.clone());
+    }
+
+    let commit_hash = match loc {
+        ResourceLocation::Local => {
+            let commit_hash = local::latest_local_commit_hash()?;
+            mark_uncommitted_changes(commit_hash)
+        }
+        // Documents are stamped with the snapshot, so it must be a commit: a
+        // branch name would not say which version of a class was used
+        ResourceLocation::Remote => remote::latest_full_commit_hash().map_err(|e| {
+            format!(
+                "Cannot get the latest commit of the resource repository: {}",
+                e
+            )
+        })?,
+    };
+    report::debug(format!("Using resources at commit {}", commit_hash));
+    SNAPSHOT.lock().unwrap().insert(*loc, commit_hash.clone());
+    Ok(commit_hash)
+}
+
+/// Fetch resources at the given commit for the rest of the run, e.g., to apply
+/// a plan made earlier.  Resources read from the local working tree can only
+/// be read as they are now, so this fails if the checkout is not at that
+/// commit
+pub(crate) fn pin_snapshot(loc: &ResourceLocation, commit_hash: &str) -> Result<(), String> {
+    if *loc == ResourceLocation::Local && !local::reads_from_objects() {
+        let current = snapshot(loc)?;
+        if current != commit_hash {
+            return Err(format!(
+                "resources were planned at commit {}, but the local repository is at {}",
+                commit_hash, current
+            ));
+        }
+    }
+
+    SNAPSHOT
+        .lock()
+        .unwrap()
+        .insert(*loc, commit_hash.to_string());
+    Ok(())
+}
+
+/// Fetch a resource as it was at a given (possibly abbreviated) commit
+pub fn fetch_resource_at(resource: &str, commit: &str, loc: &ResourceLocation) -> Option<String> {
+    let resource = strip_repo_prefix(resource);
+    // Uncommitted changes cannot be fetched again; the commit is the best we have
+    let commit = commit.strip_suffix(config::DIRTY_SUFFIX).unwrap_or(commit);
+    report::action(
+        Level::Debug,
+        Action::Fetch,
+        resource,
+        format!("Fetching {:?} at {}", resource, commit),
+    );
     match loc {
-        ResourceLocation::Local => fetch_resource_local(resource),
-        ResourceLocation::Remote => fetch_resource_remote(resource),
+        ResourceLocation::Local => local::local_resource_at(commit, resource),
+        ResourceLocation::Remote => {
+            // Raw file URLs need a full commit hash
+            let commit = if commit.len() == config::FULL_HASH_LENGTH {
+                commit.to_string()
+            } else {
+                remote::resolve_commit(commit).ok()?
+            };
+            remote::try_get_remote_resource(resource, &commit).unwrap_or_else(|e| {
+                report::warn(format!(
+                    "Cannot get remote resource {:?} at {}: {}",
+                    resource, commit, e
+                ));
+                None
+            })
+        }
     }
 }
 
-fn fetch_resource_local(resource: &str) -> String {
-    let resource_dir = local::local_resource_path();
-    let resource = resource
+/// Abbreviated hash of the latest commit to the resource repository
+/// (that is, of this run's snapshot)
+pub fn latest_commit_hash(loc: &ResourceLocation) -> Result<String, String> {
+    let snapshot = snapshot(loc)?;
+    let (commit_hash, dirty) = match snapshot.strip_suffix(config::DIRTY_SUFFIX) {
+        Some(commit_hash) => (commit_hash, config::DIRTY_SUFFIX),
+        None => (snapshot.as_str(), ""),
+    };
+    let commit_hash: String = commit_hash
+        .chars()
+        .take(config::SHORT_HASH_LENGTH)
+        .collect();
+    Ok(commit_hash + dirty)
+}
+
+fn report_fetch(resource: &str, loc: &ResourceLocation) {
+    let source = match loc {
+        ResourceLocation::Local => "local checkout",
+        ResourceLocation::Remote => "remote repository",
+    };
+    report::action(
+        Level::Debug,
+        Action::Fetch,
+        strip_repo_prefix(resource),
+        format!("Fetching {:?} from {}", resource, source),
+    );
+}
+
+fn strip_repo_prefix(resource: &str) -> &str {
+    resource
         .strip_prefix(format!("{}/{}/", config::GITHUB_USER, config::GITHUB_REPO_NAME).as_str())
-        .unwrap_or(resource);
+        .unwrap_or(resource)
+}
+
+fn local_resource_file(resource: &str) -> Result<PathBuf, String> {
+    let resource_dir = local::local_resource_path()?;
+    let resource = strip_repo_prefix(resource);
 
     // Adjoining an absolute path replaces the existing path
     // As such, we need to account for these in the resource
     let resource = Path::new(resource.trim_start_matches('/'));
-    let resource_path = resource_dir.join(resource);
-    fs::read_to_string(resource_path).unwrap()
+    Ok(resource_dir.join(resource))
 }
 
-fn fetch_resource_remote(resource: &str) -> String {
-    let resource = resource
-        .strip_prefix(format!("{}/{}/", config::GITHUB_USER, config::GITHUB_REPO_NAME).as_str())
-        .unwrap_or(resource);
-    remote::get_remote_resource(resource, config::MAIN_BRANCH)
+fn read_local(resource: &str, snapshot: &str) -> Result<Option<String>, String> {
+    if local::reads_from_objects() {
+        let commit_hash = snapshot
+            .strip_suffix(config::DIRTY_SUFFIX)
+            .unwrap_or(snapshot);
+        Ok(local::local_resource_at(
+            commit_hash,
+            strip_repo_prefix(resource),
+        ))
+    } else {
+        Ok(fs::read_to_string(local_resource_file(resource)?).ok())
+    }
+}
+
+// Resources read from the working tree may not be what was committed, so
+// documents made from them are stamped as such (like `git describe --dirty`)
+fn mark_uncommitted_changes(commit_hash: String) -> String {
+    if local::reads_from_objects() {
+        return commit_hash;
+    }
+
+    let changes = local::uncommitted_changes();
+    if changes.is_empty() {
+        return commit_hash;
+    }
+
+    let commit_hash = commit_hash + config::DIRTY_SUFFIX;
+    report::warn(format!(
+        "The local repository has uncommitted changes, so resources will be stamped {}.  Modified resources:\n{}",
+        commit_hash,
+        changes
+            .iter()
+            .map(|path| format!("  {}", path))
+            .collect::<Vec<_>>()
+            .join("\n")
+    ));
+    commit_hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every test remembers resources at its own snapshot, as the fetched
+    // resources are shared between tests
+    #[test]
+    fn fetched_by_snapshot() {
+        remember("class/arteacle.cls", "snapshot-a", Some("a".to_string()));
+        assert_eq!(
+            fetched("class/arteacle.cls", "snapshot-a"),
+            Some(Some("a".to_string()))
+        );
+        assert_eq!(fetched("class/arteacle.cls", "snapshot-b"), None);
+    }
+
+    #[test]
+    fn missing_resource_remembered() {
+        remember("templates/thesis.tex", "snapshot-c", None);
+        assert_eq!(fetched("templates/thesis.tex", "snapshot-c"), Some(None));
+    }
+
+    #[test]
+    fn fetched_with_or_without_repo_prefix() {
+        let prefixed = format!(
+            "{}/{}/class/arteacle.cls",
+            config::GITHUB_USER,
+            config::GITHUB_REPO_NAME
+        );
+        assert_eq!(strip_repo_prefix(&prefixed), "class/arteacle.cls");
+        assert_eq!(
+            strip_repo_prefix("class/arteacle.cls"),
+            "class/arteacle.cls"
+        );
+
+        remember(&prefixed, "snapshot-d", Some("d".to_string()));
+        assert_eq!(
+            fetched("class/arteacle.cls", "snapshot-d"),
+            Some(Some("d".to_string()))
+        );
+    }
+
+    #[test]
+    fn prefetch_skips_fetched() {
+        remember("class/arteacle.cls", "snapshot-e", Some("e".to_string()));
+        let resources = vec![
+            "class/arteacle.cls".to_string(),
+            "class/teamer/beamerthemetea.sty".to_string(),
+        ];
+        assert_eq!(
+            unfetched(&resources, "snapshot-e"),
+            vec!["class/teamer/beamerthemetea.sty"]
+        );
+        assert_eq!(unfetched(&resources, "snapshot-f").len(), 2);
+    }
+
+    #[test]
+    fn head_text_whole_characters() {
+        assert_eq!(
+            head_text(b"\\ProvidesClass".to_vec()).as_deref(),
+            Some("\\ProvidesClass")
+        );
+    }
+
+    #[test]
+    fn head_text_cut_off_character_dropped() {
+        let mut head = "caf\u{e9}".as_bytes().to_vec();
+        head.pop();
+        assert_eq!(head_text(head).as_deref(), Some("caf"));
+    }
+
+    #[test]
+    fn head_text_not_text() {
+        assert_eq!(head_text(vec![0x25, 0xff, 0xfe, 0x50]), None);
+    }
 }
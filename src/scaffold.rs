@@ -0,0 +1,304 @@
+// Scaffold a whole project, rather than a single document
+//
+// Which files make up a project is defined per document kind by a manifest in
+// the templates repository (templates/scaffold/<kind>).  Each line of the
+// manifest names a file relative to the project directory, optionally followed
+// by `= <resource>` to take its contents from the templates repository:
+//
+//     # Build configuration
+//     .latexmkrc = templates/scaffold/latexmkrc
+//     .gitignore
+//     references.bib
+//     figures/
+//
+// Paths ending in a slash are created as directories.  Files without a
+// resource are given sensible default contents.  If the repository has no
+// manifest for a kind, we fall back to a built-in one.
+
+use super::{
+    config,
+    file::{InstallTarget, InstalledResource},
+    kind::{self, DocumentKind},
+    plan::{self, Step},
+    report::{self, Action, Level},
+    resource::{ResourceLocation, snapshot, try_fetch_resource},
+};
+use std::{
+    fs,
+    path::{Component, Path},
+    process::{Command, Stdio},
+};
+
+const DEFAULT_LATEXMKRC: &str = "$pdf_mode = 1;\n";
+
+const DEFAULT_GITIGNORE: &str = "\
+*.aux
+*.bbl
+*.bcf
+*.blg
+*.fdb_latexmk
+*.fls
+*.log
+*.nav
+*.out
+*.pdf
+*.run.xml
+*.snm
+*.synctex.gz
+*.toc
+*.vrb
+*.xdv
+";
+
+struct ScaffoldEntry {
+    path: String,
+    resource: Option<String>,
+}
+
+fn default_manifest(kind: DocumentKind) -> &'static str {
+    match kind {
+        DocumentKind::Article | DocumentKind::Beamer => {
+            ".latexmkrc\n.gitignore\nreferences.bib\nfigures/\n"
+        }
+        DocumentKind::Letter | DocumentKind::FormalLetter => ".latexmkrc\n.gitignore\n",
+    }
+}
+
+// Parse a manifest.  Paths are relative to the project directory (even if
+// they start with a slash), and must stay within it
+fn parse_manifest(manifest: &str) -> Result<Vec<ScaffoldEntry>, String> {
+    manifest
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (path, resource) = match line.split_once('=') {
+                Some((path, resource)) => (path.trim(), Some(resource.trim().to_string())),
+                None => (line, None),
+            };
+            let path = path.trim_start_matches('/');
+            let within_project = Path::new(path)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+            if path.is_empty() || !within_project {
+                return Err(format!(
+                    "Scaffold manifest entry {:?} is not a path within the project",
+                    line
+                ));
+            }
+            Ok(ScaffoldEntry {
+                path: path.to_string(),
+                resource,
+            })
+        })
+        .collect()
+}
+
+fn default_contents(path: &str) -> &'static str {
+    match Path::new(path).file_name().and_then(|name| name.to_str()) {
+        Some(".latexmkrc") | Some("latexmkrc") => DEFAULT_LATEXMKRC,
+        Some(".gitignore") => DEFAULT_GITIGNORE,
+        _ => "",
+    }
+}
+
+fn write_entry(
+    entry: &ScaffoldEntry,
+    dir: &Path,
+    loc: &ResourceLocation,
+    dry_run: bool,
+) -> Result<(), String> {
+    let path = dir.join(&entry.path);
+    if path.exists() {
+        report::action(
+            Level::Info,
+            Action::Skip,
+            path.display(),
+            format!("{:?} already exists; leaving it alone", &path),
+        );
+        return Ok(());
+    }
+
+    if entry.path.ends_with('/') {
+        if dry_run {
+            plan::record(Step::CreateDir { path });
+        } else {
+            report::action(
+                Level::Info,
+                Action::Write,
+                path.display(),
+                format!("Creating directory {:?}", &path),
+            );
+            fs::create_dir_all(&path)
+                .map_err(|e| format!("Cannot create directory {:?}: {}", &path, e))?;
+        }
+        return Ok(());
+    }
+
+    if dry_run {
+        plan::record(Step::WriteFile {
+            path,
+            resource: entry.resource.clone(),
+            location: *loc,
+            commit: snapshot(loc)?,
+            contents: default_contents(&entry.path).to_string(),
+        });
+        return Ok(());
+    }
+
+    let contents = match &entry.resource {
+        Some(resource) => try_fetch_resource(resource, loc)?.unwrap_or_else(|| {
+            report::warn(format!(
+                "Could not find scaffold resource {:?}; using default contents for {:?}",
+                resource, &entry.path
+            ));
+            default_contents(&entry.path).to_string()
+        }),
+        None => default_contents(&entry.path).to_string(),
+    };
+
+    report::action(
+        Level::Info,
+        Action::Write,
+        path.display(),
+        format!("Writing {:?}", &path),
+    );
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, contents))
+        .map_err(|e| format!("Cannot write {:?}: {}", &path, e))
+}
+
+fn git(dir: &Path, args: &[&str]) -> bool {
+    Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Initialise a git repository in the project directory, committing everything
+/// in it
+pub fn git_init(dir: &Path, dry_run: bool) {
+    if dir.join(".git").exists() {
+        report::info(format!("{:?} is already a git repository", dir));
+        return;
+    }
+
+    if dry_run {
+        plan::record(Step::GitInit {
+            path: dir.to_path_buf(),
+        });
+        return;
+    }
+
+    report::info(format!("Initialising git repository in {:?}", dir));
+    if !git(dir, &["init"]) {
+        report::warn(format!("Could not initialise git repository in {:?}", dir));
+        return;
+    }
+    if !git(dir, &["add", "-A"]) || !git(dir, &["commit", "-m", "Initial commit"]) {
+        report::warn(format!(
+            "Could not make initial commit in {:?}; is your git identity configured?",
+            dir
+        ));
+    }
+}
+
+/// Scaffold a project of the given kind in a directory: the main document,
+/// the resources it needs, and the files listed in the kind's manifest
+pub fn scaffold(
+    kind: DocumentKind,
+    dir: &String,
+    out_file: &String,
+    resource_location: &ResourceLocation,
+    install_target: &InstallTarget,
+    dry_run: bool,
+) -> Result<Vec<InstalledResource>, String> {
+    // Read the manifest first, so that nothing is written if it is bad
+    let manifest_path = format!("{}{}", config::SCAFFOLD_PATH, kind.name());
+    let manifest = try_fetch_resource(&manifest_path, resource_location)?
+        .unwrap_or_else(|| default_manifest(kind).to_string());
+    let entries = parse_manifest(&manifest)?;
+
+    let project_dir = Path::new(dir);
+    if !project_dir.exists() {
+        if dry_run {
+            plan::record(Step::CreateDir {
+                path: project_dir.to_path_buf(),
+            });
+        } else {
+            report::action(
+                Level::Info,
+                Action::Write,
+                project_dir.display(),
+                format!("Creating directory {:?}", project_dir),
+            );
+            fs::create_dir_all(project_dir)
+                .map_err(|e| format!("Cannot create directory {:?}: {}", project_dir, e))?;
+        }
+    }
+
+    let installed = kind::write_document(
+        kind,
+        resource_location,
+        install_target,
+        dir,
+        out_file,
+        dry_run,
+    )?;
+
+    for entry in entries {
+        write_entry(&entry, project_dir, resource_location, dry_run)?;
+    }
+
+    Ok(installed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_parsed() {
+        let entries = parse_manifest(
+            "# Build configuration\n.latexmkrc = templates/scaffold/latexmkrc\n\n  .gitignore\nfigures/\n/chapters/intro.tex\n",
+        )
+        .unwrap();
+        let entries: Vec<(&str, Option<&str>)> = entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.resource.as_deref()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (".latexmkrc", Some("templates/scaffold/latexmkrc")),
+                (".gitignore", None),
+                ("figures/", None),
+                ("chapters/intro.tex", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn default_manifests_parsed() {
+        for kind in [DocumentKind::Article, DocumentKind::Letter] {
+            assert!(!parse_manifest(default_manifest(kind)).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn paths_outside_project_rejected() {
+        for manifest in [
+            "../../.bashrc",
+            "figures/../../.bashrc",
+            ".latexmkrc = templates/scaffold/latexmkrc\n../outside/",
+            "/",
+        ] {
+            assert!(parse_manifest(manifest).is_err(), "{:?}", manifest);
+        }
+    }
+}
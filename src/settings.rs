@@ -0,0 +1,312 @@
+// User settings
+//
+// These are read from a TOML file at ~/.config/mktex/config.toml (or the path
+// given by the MKTEX_CONFIG environment variable).  Every setting is optional;
+// anything not specified falls back to the defaults below, which match the
+// behaviour of mktex before the settings file existed.
+//
+// Command line options take precedence over these settings.
+
+use super::{config, report};
+use clap::ValueEnum;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+lazy_static! {
+    // Settings that cannot be read are reported, and the defaults used
+    // instead; the mktex programme checks them with Settings::load first
+    pub static ref SETTINGS: Settings = Settings::load().unwrap_or_else(|e| {
+        report::warn(format!("{}; using the default settings", e));
+        Settings::default()
+    });
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Settings {
+    pub install: InstallSettings,
+    pub layout: LayoutSettings,
+    pub build: BuildSettings,
+    pub network: NetworkSettings,
+    pub local: LocalSettings,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct InstallSettings {
+    /// Where class files, inputs, and themes are installed
+    pub to: InstallTo,
+    /// Subdirectory of the output directory used for project-local installs
+    pub project_dir: String,
+}
+
+#[derive(Deserialize, ValueEnum, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum InstallTo {
+    /// The local texmf directory (TEXMFHOME)
+    #[default]
+    Texmf,
+    /// A subdirectory of the project
+    Project,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct BuildSettings {
+    /// Engine used to compile documents; detected from what is installed if
+    /// not given
+    pub engine: Option<Engine>,
+}
+
+#[derive(Deserialize, ValueEnum, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    Latexmk,
+    Tectonic,
+    Pdflatex,
+    Xelatex,
+    Lualatex,
+}
+
+/// How resources are laid out within the texmf directory
+///
+/// The install root may contain the placeholders {user} and {repo}, which are
+/// replaced by the resource's source repository, so that e.g.
+///
+/// ```toml
+/// [layout]
+/// root = "tex/latex/{repo}"
+/// ```
+///
+/// gives each source its own subdirectory.  Individual sources (keyed by
+/// "user/repo") may also override the root and prefix entirely.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct LayoutSettings {
+    /// Install root, relative to TEXMFHOME
+    pub root: String,
+    /// Prefix stripped from resource paths in the source repository
+    pub strip_prefix: String,
+    /// Per-source overrides
+    pub sources: HashMap<String, SourceLayout>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct SourceLayout {
+    pub root: Option<String>,
+    pub strip_prefix: Option<String>,
+}
+
+impl Default for LayoutSettings {
+    fn default() -> Self {
+        Self {
+            root: config::TEXMF_LOCAL_ROOT.to_string(),
+            strip_prefix: config::RESOURCE_PARENT.to_string(),
+            sources: HashMap::new(),
+        }
+    }
+}
+
+impl LayoutSettings {
+    fn source(&self, user: &str, repo: &str) -> Option<&SourceLayout> {
+        self.sources.get(&format!("{}/{}", user, repo))
+    }
+
+    /// Install root (relative to TEXMFHOME) for resources from the given source
+    pub fn root(&self, user: &str, repo: &str) -> PathBuf {
+        let root = self
+            .source(user, repo)
+            .and_then(|source| source.root.as_ref())
+            .unwrap_or(&self.root);
+        PathBuf::from(root.replace("{user}", user).replace("{repo}", repo))
+    }
+
+    /// Prefix to strip from resource paths from the given source
+    pub fn strip_prefix(&self, user: &str, repo: &str) -> &str {
+        self.source(user, repo)
+            .and_then(|source| source.strip_prefix.as_deref())
+            .unwrap_or(&self.strip_prefix)
+    }
+}
+
+impl Default for InstallSettings {
+    fn default() -> Self {
+        Self {
+            to: InstallTo::default(),
+            project_dir: "tex".to_string(),
+        }
+    }
+}
+
+/// Where the local checkout of the resource repository (used with --local) is
+///
+/// ```toml
+/// [local]
+/// path = "~/src/tex-macros.git"
+/// rev = "master"
+/// ```
+///
+/// The checkout may be a bare repository or a git worktree.  Resources are read
+/// from the working tree, unless a ref is given (or the repository is bare), in
+/// which case they are read from git's objects at that ref.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct LocalSettings {
+    /// Path to the checkout; defaults to ~/projects/tex-macros
+    pub path: Option<PathBuf>,
+    /// Ref (branch, tag, or commit) to read resources at
+    pub rev: Option<String>,
+}
+
+/// How requests to GitHub (and doi.org) are made
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct NetworkSettings {
+    /// Seconds to wait for a whole request before giving up
+    pub timeout: u64,
+    /// Seconds to wait for a connection before giving up
+    pub connect_timeout: u64,
+    /// How many times to retry a request that failed for a transient reason
+    /// (a timeout, a dropped connection, or a server error)
+    pub retries: u32,
+    /// Milliseconds to wait before the first retry; each further retry waits
+    /// twice as long as the one before
+    pub backoff: u64,
+    /// Longest a server may ask us to wait (with Retry-After) before we give
+    /// up instead, in seconds
+    pub max_retry_after: u64,
+    /// Proxy for every request, e.g. "http://proxy.example.ac.nz:8080".  If not
+    /// given, the HTTP_PROXY, HTTPS_PROXY, ALL_PROXY, and NO_PROXY environment
+    /// variables are used
+    pub proxy: Option<String>,
+    /// Hosts to reach without the proxy, separated by commas.  This applies to
+    /// a proxy from the environment too, along with NO_PROXY
+    pub no_proxy: Option<String>,
+    /// PEM bundle of additional root certificates to trust, e.g. an
+    /// institution's internal certificate authority
+    pub ca_bundle: Option<PathBuf>,
+    /// Base URLs of mirrors of the resource repository, tried in order before
+    /// GitHub.  A mirror serves each file at "<base>/<commit>/<path>", like
+    /// https://raw.githubusercontent.com/{user}/{repo} (the placeholders are
+    /// replaced as in the layout settings).  The GitHub API must still be
+    /// reachable to tell which commit is the latest
+    pub mirrors: Vec<String>,
+    /// Keep responses on disk (see the cache module), so that unchanged
+    /// resources are not downloaded again
+    pub cache: bool,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            timeout: 30,
+            connect_timeout: 10,
+            retries: 3,
+            backoff: 500,
+            max_retry_after: 60,
+            proxy: None,
+            no_proxy: None,
+            ca_bundle: None,
+            mirrors: Vec::new(),
+            cache: true,
+        }
+    }
+}
+
+impl Settings {
+    /// Path to the user's settings file
+    pub fn path() -> Option<PathBuf> {
+        if let Ok(path) = env::var("MKTEX_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+
+        home::home_dir().map(|home| home.join(".config").join("mktex").join("config.toml"))
+    }
+
+    /// Read the user's settings file (the defaults if there is none)
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read settings file {:?}: {}", &path, e))?;
+        toml::from_str(&contents)
+            .map_err(|e| format!("Could not parse settings file {:?}: {}", &path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn layout(toml: &str) -> LayoutSettings {
+        toml::from_str::<Settings>(toml).unwrap().layout
+    }
+
+    #[test]
+    fn default_layout_is_legacy() {
+        let layout = LayoutSettings::default();
+        assert_eq!(
+            layout.root("jakewilliami", "tex-macros"),
+            PathBuf::from("tex/latex/local")
+        );
+        assert_eq!(layout.strip_prefix("jakewilliami", "tex-macros"), "class/");
+    }
+
+    #[test]
+    fn legacy_prefix_stripped() {
+        let layout = LayoutSettings::default();
+        let prefix = layout.strip_prefix("jakewilliami", "tex-macros");
+        let stripped = |resource: &str| {
+            Path::new(resource)
+                .strip_prefix(prefix)
+                .unwrap_or(Path::new(resource))
+                .to_path_buf()
+        };
+        assert_eq!(
+            stripped("class/arteacle.cls"),
+            PathBuf::from("arteacle.cls")
+        );
+        assert_eq!(
+            stripped("class/teamer/beamerthemetea.sty"),
+            PathBuf::from("teamer/beamerthemetea.sty")
+        );
+        assert_eq!(
+            stripped("macros/common.tex"),
+            PathBuf::from("macros/common.tex")
+        );
+    }
+
+    #[test]
+    fn user_and_repo_substituted() {
+        let layout = layout("[layout]\nroot = \"tex/latex/{user}/{repo}\"\n");
+        assert_eq!(
+            layout.root("jakewilliami", "tex-macros"),
+            PathBuf::from("tex/latex/jakewilliami/tex-macros")
+        );
+        assert_eq!(layout.strip_prefix("jakewilliami", "tex-macros"), "class/");
+    }
+
+    #[test]
+    fn source_overrides_layout() {
+        let layout = layout(
+            "[layout]\nroot = \"tex/latex/{repo}\"\n\n[layout.sources.\"someone/thesis\"]\nroot = \"tex/latex/thesis\"\nstrip_prefix = \"\"\n",
+        );
+        assert_eq!(
+            layout.root("someone", "thesis"),
+            PathBuf::from("tex/latex/thesis")
+        );
+        assert_eq!(layout.strip_prefix("someone", "thesis"), "");
+        assert_eq!(
+            layout.root("jakewilliami", "tex-macros"),
+            PathBuf::from("tex/latex/tex-macros")
+        );
+    }
+}
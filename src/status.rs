@@ -0,0 +1,128 @@
+// Report on installed resources
+//
+// For each kind of document, we show every resource it needs, the metadata of
+// the installed copy (from its \ProvidesClass or \ProvidesPackage line), and
+// whether that copy is in sync with the resource repository.
+
+use super::{
+    config,
+    file::{InstallTarget, LocalResource},
+    input,
+    kind::DocumentKind,
+    provides, report,
+    resource::{ResourceLocation, fetch_resource},
+    sync,
+};
+use clap::ValueEnum;
+use std::fs;
+
+struct ResourceStatus {
+    resource_path: String,
+    installed: String,
+    state: &'static str,
+}
+
+fn resource_status(resource: &LocalResource) -> Result<ResourceStatus, String> {
+    // Resources \input by classes are prefixed with the repo's owner and name
+    let resource_path = resource
+        .resource_path
+        .strip_prefix(&format!(
+            "{}/{}/",
+            config::GITHUB_USER,
+            config::GITHUB_REPO_NAME
+        ))
+        .unwrap_or(&resource.resource_path)
+        .to_string();
+
+    let local_path = resource.install_target.resources_dir()?.join(
+        resource
+            .install_target
+            .installed_name(&resource.resource_path),
+    );
+
+    let Ok(installed_contents) = fs::read_to_string(&local_path) else {
+        return Ok(ResourceStatus {
+            resource_path,
+            installed: "-".to_string(),
+            state: "not installed",
+        });
+    };
+
+    let installed = provides::parse(&installed_contents)
+        .map(|provides| provides.to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let contents = fetch_resource(&resource.resource_path, resource.resource_location)?;
+    let state = if sync::check_resource(&local_path, &contents) {
+        "in sync"
+    } else {
+        "out of sync"
+    };
+
+    Ok(ResourceStatus {
+        resource_path,
+        installed,
+        state,
+    })
+}
+
+/// Print the status of the resources needed by every kind of document
+pub fn print_status(
+    resource_location: &ResourceLocation,
+    install_target: &InstallTarget,
+) -> Result<(), String> {
+    let mut statuses: Vec<ResourceStatus> = Vec::new();
+
+    for kind in DocumentKind::value_variants() {
+        let cls = LocalResource {
+            resource_path: kind.class_resource().to_string(),
+            resource_location,
+            install_target,
+            template: None,
+        };
+        let mut resources: Vec<LocalResource> = kind
+            .extra_resources()
+            .into_iter()
+            .map(|resource_path| LocalResource {
+                resource_path,
+                resource_location,
+                install_target,
+                template: None,
+            })
+            .collect();
+        resources.push(cls.clone());
+        resources.extend(input::sourced_files(cls)?);
+
+        // Some kinds of document share resources
+        for resource in resources {
+            let status = resource_status(&resource)?;
+            if !statuses
+                .iter()
+                .any(|other| other.resource_path == status.resource_path)
+            {
+                statuses.push(status);
+            }
+        }
+    }
+
+    let path_width = statuses
+        .iter()
+        .map(|status| status.resource_path.len())
+        .max()
+        .unwrap_or(0);
+    let installed_width = statuses
+        .iter()
+        .map(|status| status.installed.len())
+        .max()
+        .unwrap_or(0);
+    for status in &statuses {
+        report::output(format!(
+            "{:<path_width$}  {:<installed_width$}  {}",
+            status.resource_path,
+            status.installed,
+            status.state,
+            path_width = path_width,
+            installed_width = installed_width
+        ));
+    }
+    Ok(())
+}
@@ -4,21 +4,25 @@
 // First, we need to find it!  We can do this manually, or calling
 // out to the kpsewhich tool
 
+use super::{
+    config,
+    plan::{self, Step},
+    report::{self, Action, Level},
+    resource::Source,
+    settings::SETTINGS,
+};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
-// Get texmf from kpsewhich
-// kpsewhich -var-value TEXMFHOME
+/// The local texmf tree, from `kpsewhich -var-value TEXMFHOME`, or None if
+/// kpsewhich cannot tell us
 pub fn texmf() -> Option<PathBuf> {
     let mut cmd = Command::new("kpsewhich");
     cmd.arg("-var-value");
     cmd.arg("TEXMFHOME");
 
-    let output = cmd
-        .stdout(Stdio::piped())
-        .output()
-        .expect("Failed to execute `kpsewhich`");
+    let output = cmd.stdout(Stdio::piped()).output().ok()?;
 
     if output.status.success() {
         let mut texmf_home = String::from_utf8_lossy(&output.stdout).into_owned();
@@ -35,43 +39,249 @@ pub fn texmf() -> Option<PathBuf> {
     }
 }
 
-pub fn texmf_local_resources() -> PathBuf {
-    let local_dir = texmf()
-        .expect("Cannot get texmf dir")
-        .join("tex")
-        .join("latex")
-        .join("local");
+// Directory in the local texmf tree into which resources from the given
+// source are installed, as configured by the layout settings
+pub(crate) fn texmf_local_resources(source: &Source) -> Result<PathBuf, String> {
+    let texmf_home = texmf().ok_or(
+        "Cannot find the local texmf directory; is kpsewhich installed?  (Run `mktex doctor` to check)",
+    )?;
+    Ok(texmf_home.join(SETTINGS.layout.root(&source.user, &source.repo)))
+}
+
+pub(crate) fn resource_in_local_texmf(resource: &PathBuf, source: &Source) -> Result<bool, String> {
+    Ok(texmf_local_resources(source)?
+        .join(resource)
+        .as_path()
+        .exists())
+}
+
+// Resources were installed into tex/latex/local/ (with the class/ prefix
+// stripped) before the layout was configurable.  If the layout has since
+// changed, move the resource from its old location into the new one so that
+// TeX does not find two copies of it.  Returns true if anything was moved
+pub(crate) fn migrate_legacy_resource(resource: &Path, source: &Source, dry_run: bool) -> bool {
+    let Some(texmf_home) = texmf() else {
+        return false;
+    };
+    let legacy_path = texmf_home.join(config::TEXMF_LOCAL_ROOT).join(
+        resource
+            .strip_prefix(config::RESOURCE_PARENT)
+            .unwrap_or(resource),
+    );
+    let new_path = texmf_home
+        .join(SETTINGS.layout.root(&source.user, &source.repo))
+        .join(resource);
+
+    if legacy_path == new_path || !legacy_path.exists() || new_path.exists() {
+        return false;
+    }
+
+    if dry_run {
+        plan::record(Step::MoveResource {
+            from: legacy_path,
+            to: new_path,
+        });
+        return true;
+    }
+
+    report::action(
+        Level::Info,
+        Action::Write,
+        new_path.display(),
+        format!(
+            "Moving resource {:?} from {:?} to {:?}",
+            resource, &legacy_path, &new_path
+        ),
+    );
+    if let Err(e) = move_file(&legacy_path, &new_path) {
+        report::error(format!("Could not move {:?}: {}", &legacy_path, e));
+        return false;
+    }
+    true
+}
+
+/// Move a file, making the destination's parent directories if needed
+pub(crate) fn move_file(from: &Path, to: &Path) -> Result<(), String> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+
+    // Renaming fails across file systems, so fall back to copying
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to).map_err(|e| e.to_string())?;
+        fs::remove_file(from).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Check whether the texmf tree has an ls-R filename database.  If it does,
+// kpathsea will only search the files listed in it, so newly written
+// resources will not be found until the database is refreshed
+pub(crate) fn has_ls_r(tree: &Path) -> bool {
+    tree.join("ls-R").as_path().exists()
+}
+
+// Refresh the filename database of the texmf tree
+// mktexlsr <tree>
+//
+// Some distributions only ship texhash (which is the same programme), so we
+// fall back to that if mktexlsr is not available.  Returns true if the
+// database was successfully refreshed
+pub(crate) fn refresh_filename_database(tree: &Path) -> bool {
+    for prog in ["mktexlsr", "texhash"] {
+        let mut cmd = Command::new(prog);
+        cmd.arg(tree);
+
+        // Try the next programme if this one does not exist
+        let Ok(output) = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).output() else {
+            continue;
+        };
+
+        return output.status.success();
+    }
 
-    // Make directory if it doesn't exist
-    if !local_dir.as_path().exists() {
-        fs::create_dir_all(&local_dir).unwrap();
+    false
+}
+
+// Find every copy of a file visible to kpathsea, in search order
+// kpsewhich -all <file>
+fn kpsewhich_all(file_name: &str) -> Vec<PathBuf> {
+    let mut cmd = Command::new("kpsewhich");
+    cmd.arg("-all");
+    cmd.arg(file_name);
+
+    // kpsewhich exits unsuccessfully if the file cannot be found at all
+    let Ok(output) = cmd.stdout(Stdio::piped()).output() else {
+        return Vec::new();
+    };
+    if !output.status.success() {
+        return Vec::new();
     }
 
-    local_dir
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| PathBuf::from(line.trim_end()))
+        .collect()
+}
+
+// Find the copy of a file that TeX will use
+// kpsewhich <file>
+pub(crate) fn kpsewhich(file_name: &str) -> Option<PathBuf> {
+    kpsewhich_all(file_name).into_iter().next()
 }
 
-pub fn resource_in_local_texmf(resource: &PathBuf) -> bool {
-    texmf_local_resources().join(resource).as_path().exists()
+/// Which file TeX will use for an installed resource
+#[derive(Debug, PartialEq)]
+pub(crate) enum Resolution {
+    /// TeX finds the installed file
+    Installed,
+    /// TeX finds other files of the same name (in search order) first
+    Shadowed(Vec<PathBuf>),
+    /// TeX does not find the file at all, e.g., because the filename database
+    /// has not been refreshed
+    NotFound,
+}
+
+// Check that kpathsea resolves the installed resource to the file we just
+// wrote, rather than a file of the same name elsewhere in TEXMFHOME or
+// TEXMFLOCAL (or the distribution itself)
+pub(crate) fn resolve_installed(installed: &Path) -> Resolution {
+    let Some(file_name) = installed.file_name() else {
+        return Resolution::NotFound;
+    };
+
+    // An out-of-date filename database may still list files that are gone
+    let copies = kpsewhich_all(&file_name.to_string_lossy())
+        .into_iter()
+        .filter(|copy| copy.exists())
+        .collect();
+    resolution(installed, copies)
+}
+
+fn resolution(installed: &Path, copies: Vec<PathBuf>) -> Resolution {
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let installed = canonical(installed);
+
+    // The first result is the one TeX will use
+    match copies.first() {
+        None => Resolution::NotFound,
+        Some(resolved) if canonical(resolved) == installed => Resolution::Installed,
+        Some(_) => Resolution::Shadowed(
+            copies
+                .into_iter()
+                .filter(|copy| canonical(copy) != installed)
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn installed_file_found_first() {
+        let installed = Path::new("/texmf/tex/latex/local/arteacle.cls");
+        let copies = vec![
+            installed.to_path_buf(),
+            PathBuf::from("/usr/share/texmf/tex/latex/arteacle.cls"),
+        ];
+        assert_eq!(resolution(installed, copies), Resolution::Installed);
+    }
+
+    #[test]
+    fn shadowed_excludes_installed_file() {
+        let installed = Path::new("/texmf/tex/latex/local/arteacle.cls");
+        let other = PathBuf::from("/texmf/tex/latex/arteacle.cls");
+        let copies = vec![other.clone(), installed.to_path_buf()];
+        assert_eq!(
+            resolution(installed, copies),
+            Resolution::Shadowed(vec![other])
+        );
+    }
+
+    #[test]
+    fn ls_r_detected() {
+        let tree = env::temp_dir().join(format!("mktex-texmf-{}", process::id()));
+        fs::create_dir_all(&tree).unwrap();
+        let without = has_ls_r(&tree);
+        fs::write(
+            tree.join("ls-R"),
+            "% ls-R -- filename database for kpathsea\n",
+        )
+        .unwrap();
+        let with = has_ls_r(&tree);
+        fs::remove_dir_all(&tree).unwrap();
+        assert!(!without);
+        assert!(with);
+    }
+
+    #[test]
+    fn not_found() {
+        let installed = Path::new("/texmf/tex/latex/local/arteacle.cls");
+        assert_eq!(resolution(installed, Vec::new()), Resolution::NotFound);
+    }
 }
 
 /*
 // Get texmf manually
 #[cfg(target_os = "macos")]
-pub fn texmf_manual() -> PathBuf {
+pub(crate) fn texmf_manual() -> PathBuf {
     home::home_dir().expect("Cannot get home directory")
         .join("Library").join("texmf")
 }
 #[cfg(any(target_os = "linux", target_os = "windows"))]
-pub fn texmf_manual() -> PathBuf {
+pub(crate) fn texmf_manual() -> PathBuf {
     home::home_dir().expect("Cannot get home directory")
         .join("texmf")
 }
 
-pub fn texmf_exists() -> bool {
+pub(crate) fn texmf_exists() -> bool {
     texmf().is_some()
 }
 
-pub fn texmf_exists_manual() -> bool {
+pub(crate) fn texmf_exists_manual() -> bool {
     texmf_manual().as_path().exists()
 }
 */
@@ -0,0 +1,383 @@
+// Bring an existing document up to date with its template
+//
+// Documents record the commit of the resource repository that they were made
+// from (see info.rs).  The template as it was at that commit is the common
+// ancestor of the document and the template as it is now, so we can apply the
+// template's changes to the document with a three-way merge, keeping whatever
+// the user has written since.  The merge itself is done by `git merge-file`.
+
+use super::{
+    config,
+    file::{self, CLASS_VERSION_RE, DOCUMENT_CLASS_RE, InstallTarget, LocalResource},
+    input,
+    kind::{self, DocumentKind},
+    plan::{self, Step},
+    provides,
+    report::{self, Action, Level},
+    resource::{ResourceLocation, fetch_resource, fetch_resource_at, latest_commit_hash, snapshot},
+};
+use clap::ValueEnum;
+use std::{
+    collections::HashSet,
+    env, fs,
+    path::Path,
+    process::{self, Command, Output},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+// Count the lines of a template that also appear in the document
+fn shared_lines(document: &str, template: &str) -> usize {
+    let document: HashSet<&str> = document.lines().map(str::trim).collect();
+    template
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && document.contains(line))
+        .count()
+}
+
+// Find the template a document was made from, and its contents at the given
+// commit.  Several kinds of document may share a class (e.g., letters), in
+// which case we choose the template that most resembles the document
+fn find_template(
+    document: &str,
+    class_resource: &str,
+    commit: &str,
+    template: Option<&str>,
+    loc: &ResourceLocation,
+) -> Option<(String, String)> {
+    let candidates: Vec<String> = match template {
+        Some(template) => vec![template.to_string()],
+        None => DocumentKind::value_variants()
+            .iter()
+            .filter(|kind| kind.class_resource() == class_resource)
+            .map(|kind| kind.template_resource().to_string())
+            .collect(),
+    };
+
+    closest_template(
+        document,
+        candidates.into_iter().filter_map(|template| {
+            let contents = fetch_resource_at(&template, commit, loc)?;
+            Some((template, contents))
+        }),
+    )
+}
+
+// Of several templates (and their contents), the one that most resembles the
+// document
+fn closest_template(
+    document: &str,
+    templates: impl Iterator<Item = (String, String)>,
+) -> Option<(String, String)> {
+    templates.max_by_key(|(_, contents)| shared_lines(document, contents))
+}
+
+// Three-way merge with `git merge-file`, returning the merged contents and
+// the number of conflicts
+fn merge(
+    ours: &str,
+    base: &str,
+    theirs: &str,
+    labels: [&str; 3],
+) -> Result<(String, usize), String> {
+    // Each merge gets its own directory, in case several run at once
+    static MERGES: AtomicUsize = AtomicUsize::new(0);
+    let dir = env::temp_dir().join(format!(
+        "mktex-upgrade-{}-{}",
+        process::id(),
+        MERGES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let output = merge_in(&dir, [ours, base, theirs], labels);
+    fs::remove_dir_all(&dir).ok();
+    let output = output?;
+
+    // The exit code is the number of conflicts, or negative on error
+    let conflicts = match output.status.code() {
+        Some(code) if code >= 0 => code as usize,
+        _ => {
+            return Err(format!(
+                "git merge-file failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+    };
+
+    Ok((
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        conflicts,
+    ))
+}
+
+// Run `git merge-file` on the three versions, written to files in a directory
+fn merge_in(dir: &Path, versions: [&str; 3], labels: [&str; 3]) -> Result<Output, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Cannot create {:?}: {}", dir, e))?;
+    let mut paths = Vec::new();
+    for (name, contents) in ["ours", "base", "theirs"].into_iter().zip(versions) {
+        let path = dir.join(name);
+        fs::write(&path, contents).map_err(|e| format!("Cannot write {:?}: {}", path, e))?;
+        paths.push(path);
+    }
+
+    Command::new("git")
+        .arg("merge-file")
+        .arg("-p")
+        .args(labels.iter().flat_map(|label| ["-L", label]))
+        .args(&paths)
+        .output()
+        .map_err(|e| format!("Failed to run git merge-file: {}", e))
+}
+
+// Update the installed class, and everything it needs, to the latest version
+fn refresh_class(
+    class_resource: &str,
+    loc: &ResourceLocation,
+    install_target: &InstallTarget,
+    dry_run: bool,
+) -> Result<(), String> {
+    let cls = LocalResource {
+        resource_path: class_resource.to_string(),
+        resource_location: loc,
+        install_target,
+        template: None,
+    };
+    let mut resources: Vec<LocalResource> = DocumentKind::value_variants()
+        .iter()
+        .filter(|kind| kind.class_resource() == class_resource)
+        .flat_map(|kind| kind.extra_resources())
+        .collect::<HashSet<String>>()
+        .into_iter()
+        .map(|resource_path| LocalResource {
+            resource_path,
+            resource_location: loc,
+            install_target,
+            template: None,
+        })
+        .collect();
+    resources.push(cls.clone());
+    resources.extend(input::sourced_files(cls)?);
+
+    for resource in &resources {
+        file::refresh_resource(resource, dry_run)?;
+    }
+    Ok(())
+}
+
+// What a document records about the class version it was made with
+struct Stamp {
+    document: String,
+    class: String,
+    commit: String,
+    /// The version as recorded, e.g., 1d72b1d (arteacle 2024/03/01 v1.4)
+    version: String,
+    dirty: bool,
+}
+
+fn read_stamp(doc: &Path) -> Result<Stamp, String> {
+    let document =
+        fs::read_to_string(doc).map_err(|e| format!("Could not read {:?}: {}", doc, e))?;
+    let class = DOCUMENT_CLASS_RE
+        .captures(&document)
+        .map(|caps| caps["class"].trim().to_string())
+        .ok_or_else(|| format!("No \\documentclass found in {:?}", doc))?;
+    let stamp = CLASS_VERSION_RE.captures(&document).ok_or_else(|| {
+        format!(
+            "{:?} has no class version stamp, so the template it was made from is unknown",
+            doc
+        )
+    })?;
+    let commit = stamp["commit"].to_string();
+    let version = stamp[0]
+        .trim_start_matches('%')
+        .trim_start()
+        .trim_start_matches("class version ")
+        .to_string();
+    let dirty = stamp.name("dirty").is_some();
+
+    Ok(Stamp {
+        class,
+        commit,
+        version,
+        dirty,
+        document,
+    })
+}
+
+// Whether a document was made from the latest commit.  Uncommitted changes
+// may differ from the commit, so are never up to date
+fn up_to_date(doc: &Path, stamp: &Stamp, latest: &str) -> bool {
+    let dirty = stamp.dirty || latest.ends_with(config::DIRTY_SUFFIX);
+    if dirty || !(latest.starts_with(&stamp.commit) || stamp.commit.starts_with(latest)) {
+        return false;
+    }
+
+    report::action(
+        Level::Info,
+        Action::Skip,
+        doc.display(),
+        format!("{:?} is already up to date ({})", doc, stamp.commit),
+    );
+    true
+}
+
+/// Apply the changes made to a document's template since the document was
+/// made, and update its class version stamp (and the installed class).  Gives
+/// false if the merge left conflicts to resolve by hand.  On a dry run, the
+/// merge is planned, but only done when the plan is applied
+pub fn upgrade(
+    doc: &Path,
+    template: Option<&str>,
+    loc: &ResourceLocation,
+    install_target: &InstallTarget,
+    dry_run: bool,
+) -> Result<bool, String> {
+    let stamp = read_stamp(doc)?;
+    let latest = latest_commit_hash(loc)?;
+    if up_to_date(doc, &stamp, &latest) {
+        return Ok(true);
+    }
+    let class_resource = kind::class_resource_named(&stamp.class);
+
+    let clean = if dry_run {
+        report::info(format!(
+            "Planning to upgrade {:?} from {} to {}",
+            doc, stamp.commit, latest
+        ));
+        plan::record(Step::UpgradeDocument {
+            path: doc.to_path_buf(),
+            template: template.map(str::to_string),
+            location: *loc,
+            commit: snapshot(loc)?,
+        });
+        true
+    } else {
+        merge_template(doc, &stamp, template, loc)?
+    };
+
+    refresh_class(&class_resource, loc, install_target, dry_run)?;
+    Ok(clean)
+}
+
+/// Merge the changes made to a document's template since the document was
+/// made into it, if it is not up to date.  Gives false if the merge left
+/// conflicts to resolve by hand
+pub(crate) fn upgrade_document(
+    doc: &Path,
+    template: Option<&str>,
+    loc: &ResourceLocation,
+) -> Result<bool, String> {
+    let stamp = read_stamp(doc)?;
+    if up_to_date(doc, &stamp, &latest_commit_hash(loc)?) {
+        return Ok(true);
+    }
+    merge_template(doc, &stamp, template, loc)
+}
+
+fn merge_template(
+    doc: &Path,
+    stamp: &Stamp,
+    template: Option<&str>,
+    loc: &ResourceLocation,
+) -> Result<bool, String> {
+    let latest = latest_commit_hash(loc)?;
+    let class_resource = kind::class_resource_named(&stamp.class);
+    let (template_path, base) = find_template(
+        &stamp.document,
+        &class_resource,
+        &stamp.commit,
+        template,
+        loc,
+    )
+    .ok_or_else(|| {
+        format!(
+            "Could not find the template for class {} at commit {}; use --template to give it",
+            stamp.class, stamp.commit
+        )
+    })?;
+
+    // The document was written with a stamp, so compare like with like
+    let base = file::stamp_class_version(&base, &stamp.version);
+    let cls_contents = fetch_resource(&class_resource, loc)?;
+    let cls_provides = provides::parse(&cls_contents);
+    let theirs = file::stamp_class_version(
+        &fetch_resource(&template_path, loc)?,
+        &file::class_version(latest.clone(), cls_provides.as_ref()),
+    );
+
+    let (merged, conflicts) = merge(
+        &stamp.document,
+        &base,
+        &theirs,
+        [
+            &doc.display().to_string(),
+            &format!("{} at {}", template_path, stamp.commit),
+            &format!("{} at {}", template_path, latest),
+        ],
+    )?;
+
+    report::action(
+        Level::Info,
+        Action::Write,
+        doc.display(),
+        format!(
+            "Upgrading {:?} from {} to {} using template {:?}",
+            doc, stamp.commit, latest, template_path
+        ),
+    );
+    fs::write(doc, merged).map_err(|e| format!("Cannot write {:?}: {}", doc, e))?;
+
+    if conflicts > 0 {
+        report::warn(format!(
+            "{} conflict(s) merging template changes into {:?}; resolve the conflict markers by hand",
+            conflicts, doc
+        ));
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LABELS: [&str; 3] = ["document", "template at old", "template at new"];
+    const BASE: &str = "\\documentclass{arteacle}\n\\title{Title}\n\\begin{document}\n\\maketitle\n\\end{document}\n";
+
+    #[test]
+    fn clean_merge_keeps_user_edits() {
+        let ours = BASE.replace("{Title}", "{My paper}");
+        let theirs = BASE.replace("\\maketitle\n", "\\maketitle\n\\tableofcontents\n");
+        let (merged, conflicts) = merge(&ours, BASE, &theirs, LABELS).unwrap();
+        assert_eq!(conflicts, 0);
+        assert!(merged.contains("\\title{My paper}"));
+        assert!(merged.contains("\\maketitle\n\\tableofcontents\n"));
+    }
+
+    #[test]
+    fn conflicting_merge_marked() {
+        let ours = BASE.replace("{Title}", "{My paper}");
+        let theirs = BASE.replace("{Title}", "{Untitled}");
+        let (merged, conflicts) = merge(&ours, BASE, &theirs, LABELS).unwrap();
+        assert_eq!(conflicts, 1);
+        assert!(merged.contains("<<<<<<< document\n\\title{My paper}\n======="));
+        assert!(merged.contains("\\title{Untitled}\n>>>>>>> template at new"));
+    }
+
+    #[test]
+    fn closest_template_chosen() {
+        let letter = "\\documentclass{lettear}\n\\begin{letter}{}\n\\opening{Dear Sir,}\n";
+        let formal = "\\documentclass{lettear}\n\\begin{letter}{}\n\\formal\n\\opening{To whom it may concern,}\n";
+        let document = "\\documentclass{lettear}\n\\begin{letter}{Jo}\n\\formal\n\\opening{To whom it may concern,}\nHello\n";
+        assert!(shared_lines(document, formal) > shared_lines(document, letter));
+
+        let templates = [("letter", letter), ("formal", formal)]
+            .map(|(name, contents)| (name.to_string(), contents.to_string()));
+        let (chosen, _) = closest_template(document, templates.into_iter()).unwrap();
+        assert_eq!(chosen, "formal");
+    }
+
+    #[test]
+    fn blank_lines_not_shared() {
+        assert_eq!(shared_lines("\n\n", "\n\n"), 0);
+    }
+}
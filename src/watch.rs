@@ -0,0 +1,190 @@
+// Rebuild a document when it (or anything it depends on) changes
+//
+// We poll modification times rather than relying on file system events, which
+// keeps us free of platform-specific dependencies.  Two sets of files are
+// watched:
+//   - the document and any files it pulls in with \input or \include; and
+//   - the source files, in the local macros checkout (if there is one), of the
+//     resources that are installed, so that edits to a class are reinstalled
+//     into texmf (or the project) before the document is rebuilt.
+
+use super::{
+    build,
+    file::{self, InstallTarget, LocalResource},
+    local, report,
+    resource::{self, ResourceLocation},
+    settings::{Engine, InstallTo},
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+lazy_static! {
+    static ref INCLUDE_RE: Regex = Regex::new(r"\\(?:input|include)\{(?P<path>[^}]+)\}").unwrap();
+}
+
+type Snapshot = HashMap<PathBuf, SystemTime>;
+
+fn snapshot(paths: &[PathBuf]) -> Snapshot {
+    paths
+        .iter()
+        .filter_map(|path| {
+            let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+            Some((path.clone(), modified))
+        })
+        .collect()
+}
+
+// Find files that have been added, modified, or removed between snapshots
+fn changed(old: &Snapshot, new: &Snapshot) -> Vec<PathBuf> {
+    let mut changed: Vec<PathBuf> = new
+        .iter()
+        .filter(|(path, modified)| old.get(*path) != Some(modified))
+        .map(|(path, _)| path.clone())
+        .collect();
+    changed.extend(old.keys().filter(|path| !new.contains_key(*path)).cloned());
+    changed
+}
+
+// The document and every file it (recursively) inputs or includes that exists
+// relative to the document's directory.  Inputs that resolve elsewhere (e.g.,
+// via texmf) are ignored here
+fn document_files(doc: &Path) -> Vec<PathBuf> {
+    let doc_dir = doc.parent().unwrap_or(Path::new("."));
+    let mut files = vec![doc.to_path_buf()];
+
+    let mut i = 0;
+    while i < files.len() {
+        if let Ok(contents) = fs::read_to_string(&files[i]) {
+            for caps in INCLUDE_RE.captures_iter(&contents) {
+                let mut path = doc_dir.join(caps["path"].trim());
+                if path.extension().is_none() {
+                    path.set_extension("tex");
+                }
+                if path.exists() && !files.contains(&path) {
+                    files.push(path);
+                }
+            }
+        }
+        i += 1;
+    }
+
+    files
+}
+
+// Take snapshots until nothing has changed for the debounce period, so that we
+// do not rebuild halfway through an editor writing several files
+fn settle(mut take: impl FnMut() -> Snapshot) -> Snapshot {
+    let mut current = take();
+    loop {
+        thread::sleep(DEBOUNCE);
+        let next = take();
+        if next == current {
+            return current;
+        }
+        current = next;
+    }
+}
+
+// Source files in the local checkout of the resources that are installed
+fn installed_sources(
+    checkout: &Path,
+    install_target: &InstallTarget,
+) -> Result<Vec<PathBuf>, String> {
+    let resources_dir = install_target.resources_dir()?;
+    Ok(local::list_local_files()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|path| {
+            resources_dir
+                .join(install_target.installed_name(&path.to_string_lossy()))
+                .exists()
+        })
+        .map(|path| checkout.join(path))
+        .collect())
+}
+
+/// Watch a document and the sources of the installed resources, rebuilding
+/// the document whenever any of them changes.  This only returns if watching
+/// cannot start
+pub fn watch(doc: &Path, engine: Option<Engine>, install_to: InstallTo) -> Result<(), String> {
+    // Sources are read from the local checkout, so resources are installed
+    // where resources from it go
+    let resource_location = ResourceLocation::Local;
+    let install_target = &InstallTarget::new(
+        install_to,
+        doc.parent().unwrap_or(Path::new(".")),
+        &resource_location,
+    );
+    let checkout = local::local_resource_dir();
+    // Resources read from git's objects only change with new commits
+    let sources = if checkout.exists() && !local::reads_from_objects() {
+        installed_sources(&checkout, install_target)?
+    } else {
+        Vec::new()
+    };
+
+    let take = || {
+        let mut files = document_files(doc);
+        files.extend(sources.iter().cloned());
+        snapshot(&files)
+    };
+
+    report::info(format!("Watching {:?} for changes", doc));
+    if !sources.is_empty() {
+        report::info(format!(
+            "Watching {} installed resource(s) in {:?}",
+            sources.len(),
+            &checkout
+        ));
+    }
+
+    // If the document cannot be built at all, there is no point watching it
+    build::build(doc, engine)?;
+    let mut state = take();
+
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        if take() == state {
+            continue;
+        }
+
+        let new_state = settle(take);
+        let changed = changed(&state, &new_state);
+        state = new_state;
+        resource::forget_fetched();
+
+        // Reinstall anything from the local checkout that has been installed
+        for path in changed.iter().filter(|path| path.exists()) {
+            // Only paths within the checkout are sources
+            let Ok(resource_path) = path.strip_prefix(&checkout) else {
+                continue;
+            };
+            let resource = LocalResource {
+                resource_path: resource_path.display().to_string(),
+                resource_location: &resource_location,
+                install_target,
+                template: None,
+            };
+            if let Err(e) = file::refresh_resource(&resource, false) {
+                report::warn(e);
+            }
+        }
+
+        if !changed.is_empty() {
+            report::info("Change detected; rebuilding");
+            if let Err(e) = build::build(doc, engine) {
+                report::error(e);
+            }
+        }
+    }
+}